@@ -4,23 +4,50 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
-use reqwest::{Client, ClientBuilder};
+use anyhow::{Context, Result};
+use reqwest::{Client, ClientBuilder, NoProxy, Proxy, header::HeaderMap, redirect};
 use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    config::app::{AppConfig, MAX_PARALLELS_REQUESTS, RETRIES},
+    auth,
+    auth::netrc::Netrc,
+    checksum::manifest::ChecksumManifest,
+    config::app::{AppConfig, MAX_PARALLELS_REQUESTS, MIN_SEGMENT_SIZE, RETRIES, SEGMENTS},
+    conflict::{CollisionPolicy, ConflictPolicy, OutputPaths},
+    error::DownloadError,
+    rate_limit::RateLimiter,
     reporter::DownloadReporter,
+    retry::RetryPolicy,
 };
 
-use super::{DownloadTask, Downloader};
+use super::{DownloadTask, Downloader, IntoUrls, TaskOutcome, TaskSettings};
 
 /// A builder for convenient construction
 pub struct DownloaderBuilder {
     client: Option<Client>,
     tasks: Vec<DownloadTask>,
-    retries: usize, // TODO: The retries feature isn't implemented yet
+    /// Outcomes of the input entries that couldn't be turned into tasks
+    invalid_entries: Vec<TaskOutcome>,
+    /// Number of the next added task
+    next_id: usize,
+    retry_policy: RetryPolicy,
     parallel_requests: usize,
+    segments: usize,
+    min_segment_size: u64,
+    checksum_manifest: Option<ChecksumManifest>,
+    require_checksum: bool,
+    headers: HeaderMap,
+    netrc: Option<Arc<Netrc>>,
+    rate_limiter: RateLimiter,
+    collision_policy: CollisionPolicy,
+    cancellation: CancellationToken,
+}
+
+impl Default for DownloaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DownloaderBuilder {
@@ -28,8 +55,19 @@ impl DownloaderBuilder {
         Self {
             client: None,
             tasks: Vec::new(),
-            retries: RETRIES,
+            invalid_entries: Vec::new(),
+            next_id: 1,
+            retry_policy: RetryPolicy::new(RETRIES),
             parallel_requests: MAX_PARALLELS_REQUESTS,
+            segments: SEGMENTS,
+            min_segment_size: MIN_SEGMENT_SIZE,
+            checksum_manifest: None,
+            require_checksum: false,
+            headers: HeaderMap::new(),
+            netrc: None,
+            rate_limiter: RateLimiter::default(),
+            collision_policy: CollisionPolicy::default(),
+            cancellation: CancellationToken::new(),
         }
     }
 
@@ -39,8 +77,15 @@ impl DownloaderBuilder {
         self
     }
 
+    /// Sets the number of retries, keeping the default backoff
     pub fn with_retries(mut self, retries: usize) -> Self {
-        self.retries = retries;
+        self.retry_policy.retries = retries;
+        self
+    }
+
+    /// Sets the number of retries and the backoff between them
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
         self
     }
 
@@ -49,76 +94,259 @@ impl DownloaderBuilder {
         self
     }
 
-    /// Adds a download task
+    /// Downloads a single file over up to `count` connections if the server supports ranges.
+    /// Files smaller than two `min_segment_size` are downloaded over one connection.
+    pub fn with_segments(mut self, count: usize, min_segment_size: u64) -> Self {
+        self.segments = count;
+        self.min_segment_size = min_segment_size;
+        self
+    }
+
+    /// Takes checksums of tasks without their own checksum from the manifest,
+    /// matching entries by the output file name
+    pub fn with_checksum_manifest(mut self, manifest: ChecksumManifest) -> Self {
+        self.checksum_manifest = Some(manifest);
+        self
+    }
+
+    /// Sends the headers with every request, unless the task overrides them
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Authenticates requests to the hosts of the `.netrc` file, unless the task sets `Authorization`
+    pub fn with_netrc(mut self, netrc: Netrc) -> Self {
+        self.netrc = Some(Arc::new(netrc));
+        self
+    }
+
+    /// Limits the aggregate speed of all tasks in bytes per second, `0` is unlimited
+    pub fn with_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limiter = RateLimiter::new(bytes_per_sec);
+        self
+    }
+
+    /// Shares the limiter with other downloaders, or keeps it to change the rate later
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Stops the download when the token is cancelled: the waiting tasks aren't started,
+    /// the running ones stop keeping their `.part` files, and both are reported as cancelled
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Sets how the build resolves tasks with the same output path.
+    /// Identical tasks (the same URL and path) are always merged
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    /// Rejects tasks without a checksum during the build
+    pub fn with_required_checksum(mut self, require: bool) -> Self {
+        self.require_checksum = require;
+        self
+    }
+
+    /// Adds a download task of a single URL or a list of mirrors, tried in order
     pub fn add_task(
         &mut self,
-        url: &str,
+        urls: impl IntoUrls,
         output: impl AsRef<Path>,
-        overwrite: bool,
+        on_conflict: ConflictPolicy,
         reporter: Arc<Mutex<dyn DownloadReporter>>,
     ) -> &mut Self {
-        self.tasks.push(DownloadTask {
-            url: url.to_string(),
-            output: output.as_ref().to_path_buf(),
-            overwrite,
-            reporter,
-        });
+        let mut urls = urls.into_urls().into_iter();
+        let url = urls.next().unwrap_or_default();
+        self.add_download_task(
+            DownloadTask::new(&url, output, on_conflict, reporter).with_mirrors(urls.collect()),
+        )
+    }
+
+    /// Adds a fully configured download task, numbering it in the order of adding
+    pub fn add_download_task(&mut self, mut task: DownloadTask) -> &mut Self {
+        task.id = self.next_id;
+        self.next_id += 1;
+        self.tasks.push(task);
+        self
+    }
+
+    /// Records an input entry that couldn't be turned into a task, so it is numbered
+    /// and reported as a rejected task
+    pub fn add_invalid_entry(&mut self, error: anyhow::Error) -> &mut Self {
+        self.invalid_entries
+            .push(TaskOutcome::invalid_entry(self.next_id, error));
+        self.next_id += 1;
         self
     }
 
     /// Adds multiple tasks from the iterator
     pub fn add_tasks<I>(&mut self, tasks: I) -> &mut Self
     where
-        I: IntoIterator<Item = (String, PathBuf, bool, Arc<Mutex<dyn DownloadReporter>>)>,
+        I: IntoIterator<Item = (String, PathBuf, ConflictPolicy, Arc<Mutex<dyn DownloadReporter>>)>,
     {
-        for (url, output, overwrite, reporter) in tasks {
-            self.add_task(&url, output, overwrite, reporter);
+        for (url, output, on_conflict, reporter) in tasks {
+            self.add_task(&url, output, on_conflict, reporter);
         }
         self
     }
 
-    /// Creates a downloader with URLs validation
-    pub fn build(self) -> Result<(Downloader, Vec<anyhow::Error>)> {
-        let mut errors = Vec::new();
+    /// Creates a downloader with URLs, output paths and checksums validation.
+    ///
+    /// Returns the failed outcomes of the rejected tasks and input entries, and the skipped outcomes
+    /// of the tasks repeating an earlier one, along with the downloader, see [`DownloadResult::add_rejected`](crate::DownloadResult::add_rejected)
+    pub fn build(self) -> Result<(Downloader, Vec<TaskOutcome>), DownloadError> {
+        let mut rejected = self.invalid_entries;
         let mut valid_tasks = Vec::new();
+        let mut outputs = OutputPaths::default();
+
+        for mut task in self.tasks {
+            if let Some(url) = std::iter::once(&task.url)
+                .chain(&task.mirrors)
+                .find(|url| !Downloader::is_valid_url(url))
+            {
+                let error = DownloadError::InvalidUrl {
+                    url: auth::redact_url(url).into_owned(),
+                };
+                rejected.push(TaskOutcome::new(&task).fail(error));
+                continue;
+            }
+
+            // Checksums are looked up by the name before disambiguation
+            if task.checksum.is_none() {
+                task.checksum = self
+                    .checksum_manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.get(&task.output))
+                    .cloned();
+            }
+
+            // The final path of a task named by the response is claimed once the response is received
+            if !task.name_from_response {
+                // The same file listed twice is downloaded once, unless the entries disagree
+                // on how, which makes them collide
+                if let Some(first) = outputs
+                    .duplicate_of(&task.output, task.id, &task.url)
+                    .and_then(|id| valid_tasks.iter().find(|other: &&DownloadTask| other.id == id))
+                    .filter(|first| first.checksum == task.checksum && first.on_conflict == task.on_conflict)
+                {
+                    let reason = DownloadError::DuplicateTask {
+                        path: task.output.clone(),
+                        id: first.id,
+                    };
+                    rejected.push(TaskOutcome::new(&task).skip(reason));
+                    continue;
+                }
+                if let Err(error) = outputs.claim(&mut task.output, task.id, &task.url, self.collision_policy)
+                {
+                    rejected.push(TaskOutcome::new(&task).fail(error));
+                    continue;
+                }
+            }
 
-        for task in self.tasks {
-            if Downloader::is_valid_url(&task.url) {
-                valid_tasks.push(task);
-            } else {
-                errors.push(anyhow::anyhow!("Invalid URL: {}", task.url));
+            if self.require_checksum && task.checksum.is_none() {
+                let error = DownloadError::MissingChecksum {
+                    path: task.output.clone(),
+                    url: task.redacted_url().into_owned(),
+                };
+                rejected.push(TaskOutcome::new(&task).fail(error));
+                continue;
             }
+
+            valid_tasks.push(task);
         }
 
-        if valid_tasks.is_empty() && errors.is_empty() {
-            return Err(anyhow::anyhow!("No download tasks provided"));
+        if valid_tasks.is_empty() && rejected.is_empty() {
+            return Err(DownloadError::NoTasks);
         }
 
-        let client = self.client.unwrap_or_else(Client::new);
+        let client = self.client.unwrap_or_default();
         let downloader = Downloader {
             tasks: valid_tasks,
+            next_id: self.next_id,
             client,
             parallel_requests: Arc::new(Semaphore::new(self.parallel_requests)),
+            settings: TaskSettings {
+                retry_policy: self.retry_policy,
+                segments: self.segments,
+                min_segment_size: self.min_segment_size,
+                headers: self.headers,
+                netrc: self.netrc,
+                rate_limiter: self.rate_limiter,
+                collision_policy: self.collision_policy,
+                outputs: Arc::new(Mutex::new(outputs)),
+                cancellation: self.cancellation,
+            },
         };
 
-        Ok((downloader, errors))
+        rejected.sort_by_key(|outcome| outcome.id);
+        Ok((downloader, rejected))
     }
 }
 
 pub fn build_client(config: &AppConfig) -> Result<Client> {
-    let builder = ClientBuilder::new();
-    Ok(builder
-        .timeout(Duration::from_secs(config.download.timeout_secs))
-        .connect_timeout(Duration::from_secs(config.download.connect_timeout_secs))
-        .build()?)
+    let config = &config.download;
+    let redirect_policy = if config.follow_redirects {
+        redirect::Policy::limited(config.max_redirects)
+    } else {
+        redirect::Policy::none()
+    };
+
+    let mut builder = ClientBuilder::new()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .redirect(redirect_policy)
+        .gzip(config.compression)
+        .brotli(config.compression)
+        .deflate(config.compression)
+        .cookie_store(config.cookies);
+
+    if !config.user_agent.is_empty() {
+        builder = builder.user_agent(&config.user_agent);
+    }
+
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    // Explicit proxies replace the ones from the environment variables
+    let no_proxy = NoProxy::from_string(&config.no_proxy.join(","));
+    let configure = |proxy: reqwest::Result<Proxy>, url: &str| -> Result<Proxy> {
+        Ok(proxy
+            .with_context(|| format!("Invalid proxy: {}", auth::redact_url(url)))?
+            .no_proxy(no_proxy.clone()))
+    };
+
+    if let Some(url) = &config.proxy {
+        builder = builder.proxy(configure(Proxy::all(url), url)?);
+    }
+    if let Some(url) = &config.http_proxy {
+        builder = builder.proxy(configure(Proxy::http(url), url)?);
+    }
+    if let Some(url) = &config.https_proxy {
+        builder = builder.proxy(configure(Proxy::https(url), url)?);
+    }
+
+    Ok(builder.build()?)
 }
 
-impl From<&AppConfig> for DownloaderBuilder {
-    fn from(value: &AppConfig) -> Self {
-        let client = build_client(&value).expect("Failed to build reqwest::Client from config");
-        Self::new()
+impl TryFrom<&AppConfig> for DownloaderBuilder {
+    type Error = anyhow::Error;
+
+    /// Fails if the HTTP client can't be built from the config, e.g. because of an invalid proxy
+    fn try_from(value: &AppConfig) -> Result<Self> {
+        let client = build_client(value)?;
+        Ok(Self::new()
             .with_parallel_requests(value.download.parallel_requests)
             .with_retries(value.download.retries)
-            .with_client(client)
+            .with_segments(value.download.segments, value.download.min_segment_size)
+            .with_rate_limit(value.download.max_bytes_per_sec)
+            .with_collision_policy(value.download.on_collision)
+            .with_client(client))
     }
 }
@@ -0,0 +1,281 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use sha2::Digest;
+use tokio::io::AsyncReadExt;
+
+pub mod manifest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// Length of the digest in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 | Self::Blake3 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = anyhow::Error;
+
+    /// Accepts names like `sha256`, `SHA-256` or `sha_256`
+    fn from_str(s: &str) -> Result<Self> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+
+        match name.as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            "blake3" | "b3" => Ok(Self::Blake3),
+            _ => Err(anyhow::anyhow!("Unsupported checksum algorithm: {}", s)),
+        }
+    }
+}
+
+impl Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The expected digest of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    pub fn new(algorithm: ChecksumAlgorithm, hex_digest: &str) -> Result<Self> {
+        let digest = hex::decode(hex_digest.trim())
+            .with_context(|| format!("Invalid {} digest: {}", algorithm, hex_digest))?;
+
+        if digest.len() != algorithm.digest_len() {
+            anyhow::bail!(
+                "Invalid {} digest length: expected {} hex characters, got {}",
+                algorithm,
+                algorithm.digest_len() * 2,
+                hex_digest.trim().len()
+            );
+        }
+
+        Ok(Self { algorithm, digest })
+    }
+
+    pub fn hex(&self) -> String {
+        hex::encode(&self.digest)
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = anyhow::Error;
+
+    /// Parses `<algorithm>:<hex>`, e.g. `sha256:e3b0c442...`
+    fn from_str(s: &str) -> Result<Self> {
+        let (algorithm, digest) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid checksum '{}', expected <algorithm>:<hex>", s))?;
+
+        Self::new(algorithm.parse()?, digest)
+    }
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex())
+    }
+}
+
+/// Computes the digest incrementally
+pub enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Self::Md5(md5::Md5::new()),
+            ChecksumAlgorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            ChecksumAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Self::Sha512(sha2::Sha512::new()),
+            ChecksumAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha512(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Feeds the whole content of the file
+    pub async fn update_from_file(&mut self, path: &Path) -> Result<()> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("Failed to open file for hashing: {}", path.display()))?;
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .await
+                .with_context(|| format!("Failed to read file for hashing: {}", path.display()))?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..read]);
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Md5(hasher) => hasher.finalize().to_vec(),
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha512(hasher) => hasher.finalize().to_vec(),
+            Self::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Computes the digest of the file
+pub async fn hash_file(path: &Path, algorithm: ChecksumAlgorithm) -> Result<Vec<u8>> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update_from_file(path).await?;
+    Ok(hasher.finalize())
+}
+
+/// The digest of the downloaded file differs from the expected one
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub path: PathBuf,
+    pub expected: Checksum,
+    pub actual: Checksum,
+}
+
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch for {}: expected {}, got {}",
+            self.path.display(),
+            self.expected,
+            self.actual.hex()
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// The downloaded file isn't of the expected size
+#[derive(Debug)]
+pub struct SizeMismatch {
+    pub path: PathBuf,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl Display for SizeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Size mismatch for {}: expected {} bytes, got {}",
+            self.path.display(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+impl std::error::Error for SizeMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algorithm: ChecksumAlgorithm, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(data);
+        hex::encode(hasher.finalize())
+    }
+
+    #[test]
+    fn test_known_digests() {
+        let data = b"abc";
+        assert_eq!(
+            digest(ChecksumAlgorithm::Md5, data),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            digest(ChecksumAlgorithm::Sha1, data),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            digest(ChecksumAlgorithm::Sha256, data),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest(ChecksumAlgorithm::Blake3, data),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digest(ChecksumAlgorithm::Sha512, data).len(), 128);
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let checksum: Checksum = "SHA256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            .parse()
+            .unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Sha256);
+        assert_eq!(
+            checksum.to_string(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert!("md5:900150983cd24fb0d6963f7d28e17f72".parse::<Checksum>().is_ok());
+        assert!("sha256:abc".parse::<Checksum>().is_err());
+        assert!("crc32:abcdef01".parse::<Checksum>().is_err());
+        assert!("ba7816bf8f01cfea".parse::<Checksum>().is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+
+use super::{Checksum, ChecksumAlgorithm};
+
+/// Checksums of files published next to them, e.g. `SHA256SUMS`.
+///
+/// Supports the GNU coreutils format (`<hex>  <file>` or `<hex> *<file>`)
+/// and the BSD format (`SHA256 (<file>) = <hex>`).
+#[derive(Debug, Default, Clone)]
+pub struct ChecksumManifest {
+    entries: HashMap<String, Checksum>,
+}
+
+impl ChecksumManifest {
+    /// Parses the manifest content.
+    ///
+    /// `name` is the manifest file name or URL, used to determine the algorithm of the GNU format
+    /// (`SHA512SUMS`, `file.md5`, `B3SUMS`...). If it says nothing, the algorithm is guessed from
+    /// the digest length.
+    pub fn parse(content: &str, name: &str) -> Result<Self> {
+        let name_algorithm = algorithm_from_name(name);
+        let mut entries = HashMap::new();
+
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (file, checksum) = parse_bsd_line(line)
+                .or_else(|| parse_gnu_line(line, name_algorithm))
+                .transpose()
+                .and_then(|entry| entry.ok_or_else(|| anyhow::anyhow!("Unrecognized format")))
+                .with_context(|| format!("Invalid line {} in checksum manifest {}", line_num + 1, name))?;
+
+            entries.insert(base_name(&file).to_string(), checksum);
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Looks up the checksum by the file name, ignoring directories in both the manifest and `file`
+    pub fn get(&self, file: &Path) -> Option<&Checksum> {
+        let name = file.file_name()?.to_str()?;
+        self.entries.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// `SHA256 (file.tar.gz) = <hex>`
+fn parse_bsd_line(line: &str) -> Option<Result<(String, Checksum)>> {
+    let (algorithm, rest) = line.split_once(" (")?;
+    let (file, digest) = rest.rsplit_once(')')?;
+    let digest = digest.trim_start().strip_prefix('=')?;
+
+    Some(
+        algorithm
+            .trim()
+            .parse()
+            .and_then(|algorithm| Checksum::new(algorithm, digest.trim()))
+            .map(|checksum| (file.to_string(), checksum)),
+    )
+}
+
+/// `<hex>  file.tar.gz` or `<hex> *file.tar.gz`.
+///
+/// Lines starting with `\` contain escaped file names.
+fn parse_gnu_line(line: &str, algorithm: Option<ChecksumAlgorithm>) -> Option<Result<(String, Checksum)>> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let (digest, file) = line.split_once(' ')?;
+    let file = file.strip_prefix([' ', '*']).unwrap_or(file);
+    let file = if escaped {
+        file.replace("\\n", "\n").replace("\\\\", "\\")
+    } else {
+        file.to_string()
+    };
+
+    let algorithm = match algorithm.or_else(|| algorithm_from_len(digest.len())) {
+        Some(algorithm) => algorithm,
+        None => {
+            return Some(Err(anyhow::anyhow!(
+                "Can't determine the algorithm of {}",
+                digest
+            )));
+        }
+    };
+
+    Some(Checksum::new(algorithm, digest).map(|checksum| (file, checksum)))
+}
+
+fn algorithm_from_name(name: &str) -> Option<ChecksumAlgorithm> {
+    let name = base_name(name).to_lowercase();
+    let name = name.trim_end_matches("sums").trim_end_matches("sum");
+
+    [
+        ("sha256", ChecksumAlgorithm::Sha256),
+        ("sha512", ChecksumAlgorithm::Sha512),
+        ("sha1", ChecksumAlgorithm::Sha1),
+        ("md5", ChecksumAlgorithm::Md5),
+        ("blake3", ChecksumAlgorithm::Blake3),
+        ("b3", ChecksumAlgorithm::Blake3),
+    ]
+    .into_iter()
+    .find(|(suffix, _)| name.ends_with(suffix))
+    .map(|(_, algorithm)| algorithm)
+}
+
+fn algorithm_from_len(hex_len: usize) -> Option<ChecksumAlgorithm> {
+    match hex_len {
+        32 => Some(ChecksumAlgorithm::Md5),
+        40 => Some(ChecksumAlgorithm::Sha1),
+        64 => Some(ChecksumAlgorithm::Sha256),
+        128 => Some(ChecksumAlgorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// The last component of a path or URL
+fn base_name(path: &str) -> &str {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+
+    #[test]
+    fn test_parse_gnu_format() {
+        let content = format!("# comment\n{SHA256}  app.tar.gz\n{MD5} *dist/app.zip\n\n");
+        let manifest = ChecksumManifest::parse(&content, "https://example.com/CHECKSUMS").unwrap();
+
+        assert_eq!(manifest.len(), 2);
+        let checksum = manifest.get(Path::new("downloads/app.tar.gz")).unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Sha256);
+        assert_eq!(checksum.hex(), SHA256);
+        assert_eq!(
+            manifest.get(Path::new("app.zip")).unwrap().algorithm,
+            ChecksumAlgorithm::Md5
+        );
+        assert!(manifest.get(Path::new("other.zip")).is_none());
+    }
+
+    #[test]
+    fn test_algorithm_from_manifest_name() {
+        let content = format!("{SHA256}  app.tar.gz\n");
+
+        let manifest = ChecksumManifest::parse(&content, "B3SUMS").unwrap();
+        let checksum = manifest.get(Path::new("app.tar.gz")).unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Blake3);
+
+        let manifest = ChecksumManifest::parse(&content, "/tmp/app.tar.gz.sha256").unwrap();
+        let checksum = manifest.get(Path::new("app.tar.gz")).unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Sha256);
+
+        assert!(ChecksumManifest::parse(&content, "MD5SUMS").is_err());
+    }
+
+    #[test]
+    fn test_parse_bsd_format() {
+        let content = format!("SHA256 (app (1).tar.gz) = {SHA256}\nMD5 (app.zip) = {MD5}\n");
+        let manifest = ChecksumManifest::parse(&content, "CHECKSUMS").unwrap();
+
+        assert_eq!(manifest.get(Path::new("app (1).tar.gz")).unwrap().hex(), SHA256);
+        assert_eq!(manifest.get(Path::new("app.zip")).unwrap().hex(), MD5);
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let error = ChecksumManifest::parse("not a checksum\n", "SHA256SUMS").unwrap_err();
+        assert!(format!("{:#}", error).contains("line 1"), "{:#}", error);
+    }
+}
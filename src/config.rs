@@ -8,8 +8,9 @@ use directories::{BaseDirs, ProjectDirs};
 
 pub mod app;
 mod cli;
+pub mod validation;
 
-pub use app::LogLevel;
+pub use app::{LogLevel, OutputFormat};
 pub use cli::{CliConfig, IntoOverwrite};
 
 use crate::config::app::{AppConfig, TomlConfig};
@@ -124,8 +125,16 @@ pub fn load_config(args: &CliConfig) -> anyhow::Result<AppConfig> {
         toml_config = TomlConfig::load_from_path(another_config)?;
     }
 
+    // Like `wget -P`, the directory given on the command line is created,
+    // while the one of the config file must exist
+    if let Some(dir) = &args.dir {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the --dir directory: {}", dir.display()))?;
+    }
+
+    // Command line arguments must satisfy the same rules as the config
     args.into_overwrite(&mut toml_config);
-    Ok(AppConfig::from(toml_config))
+    Ok(AppConfig::from(toml_config.validate()?))
 }
 
 /// Loads config from the first location found.
@@ -157,10 +166,36 @@ where
 
 #[cfg(test)]
 mod tests {
+    use clap::Parser;
+
     use super::*;
 
     #[test]
     fn test_default_path() {
         println!("{:#?}", get_config_paths());
     }
+
+    #[test]
+    fn test_missing_dir_is_created() {
+        let base = std::env::temp_dir().join(format!("dw-test-{}-create-dir", std::process::id()));
+        let config = base.with_extension("toml");
+        std::fs::write(&config, "").unwrap();
+        let dir = base.join("nested");
+
+        let args = CliConfig::parse_from([
+            "dw",
+            "https://example.com/a.txt",
+            "--config",
+            config.to_str().unwrap(),
+            "-d",
+            dir.to_str().unwrap(),
+        ]);
+        let result = load_config(&args);
+        let created = dir.is_dir();
+        std::fs::remove_dir_all(&base).ok();
+        std::fs::remove_file(&config).ok();
+
+        assert_eq!(result.unwrap().download.download_dir, Some(dir));
+        assert!(created);
+    }
 }
@@ -2,9 +2,14 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
-use crate::config::app::{AppConfig, LogLevel, TomlConfig};
-
-use super::app;
+use crate::{
+    checksum::Checksum,
+    config::app::{LogLevel, OutputFormat, TomlConfig},
+    conflict::ConflictPolicy,
+    input::InputFormat,
+    rate_limit,
+    report::ReportFormat,
+};
 
 // # Important
 // It is important to avoid adding the same boolean type fields to both
@@ -12,17 +17,43 @@ use super::app;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct CliConfig {
-    /// URL
-    pub source: String,
+    /// URLs and files with lists of downloads, `-` reads the list from stdin
+    #[arg(required = true, value_name = "SOURCE")]
+    pub sources: Vec<String>,
+
+    /// Output file of a single URL
+    #[arg(short, long, value_name = "FILE", conflicts_with = "dir")]
+    pub output: Option<PathBuf>,
 
-    /// Target filepath (existing directories will be treated as the target location)
-    pub target: Option<PathBuf>,
+    /// Directory to save the files to, created if it doesn't exist
+    #[arg(short, long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
 
     /// Silent mode
     #[arg(short, long)]
     pub silent: bool,
 
-    /// [NOT IMPLEMENTED] Resume failed or cancelled download (partial sanity check)
+    /// How to report the progress: progress bars or JSON Lines events
+    #[arg(long, value_name = "FORMAT")]
+    pub output_format: Option<OutputFormat>,
+
+    /// Writes the JSON Lines events to the file instead of stdout
+    #[arg(long, value_name = "FILE")]
+    pub events_file: Option<PathBuf>,
+
+    /// Writes the results of every download to the file
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Format of the report, detected by the extension by default (`.csv`, `.xml` for JUnit)
+    #[arg(long, value_name = "FORMAT", requires = "report")]
+    pub report_format: Option<ReportFormat>,
+
+    /// Format of the source files, detected by the extension by default
+    #[arg(long, value_name = "FORMAT")]
+    pub input_format: Option<InputFormat>,
+
+    /// Resume failed or cancelled download (partial sanity check)
     #[arg(short, long)]
     pub resume: bool,
 
@@ -30,26 +61,181 @@ pub struct CliConfig {
     #[arg(long)]
     pub config: Option<String>,
 
-    /// Overwrite if the file already exists
-    #[arg(short, long)]
+    /// Overwrite if the file already exists, same as `--on-conflict overwrite`
+    #[arg(short, long, conflicts_with = "on_conflict")]
     pub force: bool,
+
+    /// What to do if the file already exists
+    #[arg(long, value_name = "POLICY")]
+    pub on_conflict: Option<ConflictPolicy>,
+
+    /// Expected checksum of the file in single URL mode, e.g. `sha256:<hex>`.
+    /// Supported algorithms: sha256, sha512, sha1, md5, blake3
+    #[arg(long, value_name = "ALGO:HEX")]
+    pub checksum: Option<Checksum>,
+
+    /// Checksum manifest (SHA256SUMS, *.sha256, CHECKSUMS...) as a path or URL.
+    /// Entries are matched with the downloaded files by name
+    #[arg(long, value_name = "PATH|URL")]
+    pub checksum_file: Option<String>,
+
+    /// Fail downloads without a checksum
+    #[arg(long)]
+    pub require_checksum: bool,
+
+    /// Number of connections per file, if the server supports ranges
+    #[arg(long, value_name = "N")]
+    pub segments: Option<usize>,
+
+    /// Limit the aggregate download speed, e.g. `500K` or `2M` (bytes per second)
+    #[arg(long, value_name = "RATE", value_parser = rate_limit::parse_rate)]
+    pub limit_rate: Option<u64>,
+
+    /// Don't follow redirects
+    #[arg(long)]
+    pub no_redirects: bool,
+
+    /// Maximum number of redirects to follow
+    #[arg(long, value_name = "N")]
+    pub max_redirects: Option<usize>,
+
+    /// Request a compressed response and decode it (gzip, brotli, deflate)
+    #[arg(long)]
+    pub compressed: bool,
+
+    /// User-Agent header, an empty string disables it
+    #[arg(short = 'U', long, value_name = "AGENT")]
+    pub user_agent: Option<String>,
+
+    /// Use HTTP/2 without negotiation
+    #[arg(long = "http2-prior-knowledge")]
+    pub http2: bool,
+
+    /// Proxy for all requests: http://, https:// or socks5:// URL
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Comma-separated hosts that bypass the proxy
+    #[arg(long, value_name = "HOSTS", value_delimiter = ',')]
+    pub no_proxy: Vec<String>,
+
+    /// Keep cookies between requests
+    #[arg(long = "cookies")]
+    pub cookie_store: bool,
+
+    /// Name files by the server response (Content-Disposition, redirects, Content-Type)
+    #[arg(long = "content-disposition")]
+    pub name_from_response: bool,
+
+    /// Extra request header, can be repeated: `-H "Name: value"`
+    #[arg(short = 'H', long = "header", value_name = "NAME: VALUE")]
+    pub headers: Vec<String>,
+
+    /// Basic authentication credentials
+    #[arg(long, value_name = "USER[:PASSWORD]")]
+    pub user: Option<String>,
+
+    /// Bearer token for the Authorization header
+    #[arg(long, value_name = "TOKEN", conflicts_with_all = ["user", "bearer_file"])]
+    pub bearer: Option<String>,
+
+    /// Reads the bearer token from a file, keeping it out of the shell history
+    #[arg(long, value_name = "PATH", conflicts_with = "user")]
+    pub bearer_file: Option<PathBuf>,
     //
     // TODO: Add UI arguments to Cli
     //
 }
 
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoOverwrite<T> {
     /// Overwrites the `target` fields with values from `self` (where they are set).
     /// Returns `&mutT` for chained calls
-    fn into_overwrite<'a, 'b>(&'a self, target: &'b mut T) -> &'b mut T;
+    fn into_overwrite<'b>(&self, target: &'b mut T) -> &'b mut T;
 }
 
 impl IntoOverwrite<TomlConfig> for CliConfig {
-    fn into_overwrite<'a, 'b>(&'a self, target: &'b mut TomlConfig) -> &'b mut TomlConfig {
+    fn into_overwrite<'b>(&self, target: &'b mut TomlConfig) -> &'b mut TomlConfig {
         if self.silent {
             target.general.log_level = LogLevel::Silent;
         }
 
+        if let Some(output_format) = self.output_format {
+            target.general.output_format = output_format;
+        }
+
+        if let Some(segments) = self.segments {
+            target.download.segments = segments;
+        }
+
+        let download = &mut target.download;
+
+        if let Some(dir) = &self.dir {
+            download.download_dir = Some(dir.clone());
+        }
+        if self.force {
+            download.on_conflict = ConflictPolicy::Overwrite;
+        }
+        if let Some(on_conflict) = self.on_conflict {
+            download.on_conflict = on_conflict;
+        }
+        if let Some(limit_rate) = self.limit_rate {
+            download.max_bytes_per_sec = limit_rate;
+        }
+
+        if self.no_redirects {
+            download.follow_redirects = false;
+        }
+        if let Some(max_redirects) = self.max_redirects {
+            download.max_redirects = max_redirects;
+        }
+        if self.compressed {
+            download.compression = true;
+        }
+        if let Some(user_agent) = &self.user_agent {
+            download.user_agent = user_agent.clone();
+        }
+        if self.http2 {
+            download.http2_prior_knowledge = true;
+        }
+        if let Some(proxy) = &self.proxy {
+            download.proxy = Some(proxy.clone());
+        }
+        if !self.no_proxy.is_empty() {
+            download.no_proxy = self.no_proxy.clone();
+        }
+        if self.cookie_store {
+            download.cookies = true;
+        }
+        if self.name_from_response {
+            download.content_disposition = true;
+        }
+
         target
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiple_sources() {
+        let args =
+            CliConfig::try_parse_from(["dw", "https://example.com/a.txt", "list.txt", "-", "-d", "out"])
+                .unwrap();
+
+        assert_eq!(args.sources, ["https://example.com/a.txt", "list.txt", "-"]);
+        assert_eq!(args.dir, Some("out".into()));
+        assert_eq!(args.output, None);
+
+        let mut config = TomlConfig::default();
+        args.into_overwrite(&mut config);
+        assert_eq!(config.download.download_dir, Some("out".into()));
+
+        assert!(CliConfig::try_parse_from(["dw"]).is_err());
+        assert!(
+            CliConfig::try_parse_from(["dw", "https://example.com", "-o", "a.txt", "-d", "out"]).is_err()
+        );
+    }
+}
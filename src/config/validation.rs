@@ -0,0 +1,111 @@
+use std::fmt::{Debug, Display};
+
+use indicatif::ProgressStyle;
+use unicode_width::UnicodeWidthStr;
+
+/// A single problem of the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// TOML path of the key, e.g. `download.parallel_requests`
+    pub key: String,
+    /// The offending value as written in TOML
+    pub value: String,
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}: {}", self.key, self.value, self.message)
+    }
+}
+
+/// All problems found in the config
+#[derive(Debug)]
+pub struct InvalidConfig {
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid config ({} errors):", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// Collects config problems instead of stopping at the first one
+#[derive(Default)]
+pub(super) struct Validator {
+    issues: Vec<ConfigIssue>,
+}
+
+impl Validator {
+    pub fn issue(&mut self, key: impl Into<String>, value: impl Debug, message: impl Into<String>) {
+        self.issues.push(ConfigIssue {
+            key: key.into(),
+            value: format!("{:?}", value),
+            message: message.into(),
+        });
+    }
+
+    pub fn positive<T>(&mut self, key: &str, value: T)
+    where
+        T: Debug + Default + PartialEq,
+    {
+        if value == T::default() {
+            self.issue(key, value, "must be greater than 0");
+        }
+    }
+
+    pub fn templates(&mut self, key: &str, templates: &[String]) {
+        for (i, template) in templates.iter().enumerate() {
+            if let Err(err) = ProgressStyle::with_template(template) {
+                self.issue(
+                    format!("{}[{}]", key, i),
+                    template,
+                    format!("invalid template: {}", err),
+                );
+            }
+        }
+    }
+
+    /// Spinners need at least two tick chars
+    pub fn tick_chars(&mut self, key: &str, chars: &[String]) {
+        for (i, chars) in chars.iter().enumerate() {
+            if chars.chars().count() < 2 {
+                self.issue(format!("{}[{}]", key, i), chars, "at least 2 chars required");
+            }
+        }
+    }
+
+    /// Progress bars need at least two chars of equal width
+    pub fn progress_chars(&mut self, key: &str, chars: &[String]) {
+        for (i, chars) in chars.iter().enumerate() {
+            let mut widths = chars.chars().map(|c| c.to_string().width());
+
+            if chars.chars().count() < 2 {
+                self.issue(format!("{}[{}]", key, i), chars, "at least 2 chars required");
+            } else if let Some(first) = widths.next()
+                && widths.any(|width| width != first)
+            {
+                self.issue(
+                    format!("{}[{}]", key, i),
+                    chars,
+                    "all chars must have the same width",
+                );
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), InvalidConfig> {
+        if self.issues.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig { issues: self.issues })
+        }
+    }
+}
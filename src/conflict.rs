@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth, error::DownloadError};
+
+/// Maximum number of candidate names tried before giving up
+const MAX_CANDIDATES: usize = 10_000;
+
+/// What to do when the output file already exists
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    /// Fail the download
+    #[default]
+    Fail,
+    /// Replace the existing file
+    Overwrite,
+    /// Save to the first free name: `name (1).ext`, `name (2).ext`...
+    Rename,
+    /// Keep the existing file and treat the download as successful
+    Skip,
+    /// Move the existing file to `name.ext.~1~`, `name.ext.~2~`... and download to its place
+    Backup,
+}
+
+/// What to do with tasks of a downloader that write to the same output path
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// Reject every task after the first one as a validation error
+    #[default]
+    Reject,
+    /// Save the files of the later tasks as `name (1).ext`, `name (2).ext`...
+    Rename,
+}
+
+/// Output paths of the tasks of a downloader, shared by the tasks named by the response,
+/// whose paths are only known during the download
+#[derive(Debug, Default)]
+pub(crate) struct OutputPaths {
+    /// Number and URL of the task writing to the absolute path
+    tasks: HashMap<PathBuf, (usize, String)>,
+}
+
+impl OutputPaths {
+    /// Returns the number of another task with the same URL that already writes to the path
+    pub(crate) fn duplicate_of(&self, output: &Path, id: usize, url: &str) -> Option<usize> {
+        self.tasks
+            .get(&absolute_path(output))
+            .filter(|(other, other_url)| *other != id && other_url == url)
+            .map(|(other, _)| *other)
+    }
+
+    /// Claims the output for the task, renaming it to the first free `name (n).ext`
+    /// or failing if another task writes to it, depending on the `policy`
+    pub(crate) fn claim(
+        &mut self,
+        output: &mut PathBuf,
+        id: usize,
+        url: &str,
+        policy: CollisionPolicy,
+    ) -> Result<(), DownloadError> {
+        if let Some((_, used_by)) = self
+            .tasks
+            .get(&absolute_path(output))
+            .filter(|(other, _)| *other != id)
+        {
+            match policy {
+                CollisionPolicy::Reject => {
+                    return Err(DownloadError::OutputCollision {
+                        path: output.clone(),
+                        used_by: auth::redact_url(used_by).into_owned(),
+                        url: auth::redact_url(url).into_owned(),
+                    });
+                }
+                CollisionPolicy::Rename => {
+                    *output = (1..)
+                        .map(|n| numbered(output, n))
+                        .find(|path| !self.tasks.contains_key(&absolute_path(path)))
+                        .expect("Infinite iterator of names");
+                }
+            }
+        }
+        self.tasks.insert(absolute_path(output), (id, url.to_string()));
+        Ok(())
+    }
+}
+
+/// The path relative to the current directory, so that `file.zip` and `./file.zip` are the same
+fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Reserves the first free path among `output`, `name (1).ext`, `name (2).ext`...
+/// by creating an empty file, so concurrent tasks never pick the same name
+pub async fn reserve_free_path(output: &Path) -> Result<PathBuf> {
+    for candidate in
+        std::iter::once(output.to_path_buf()).chain((1..MAX_CANDIDATES).map(|n| numbered(output, n)))
+    {
+        if create_new(&candidate).await? {
+            return Ok(candidate);
+        }
+    }
+    anyhow::bail!("No free name for {}", output.display())
+}
+
+/// Moves the existing `output` to the first free `name.ext.~N~`, returning the path of the backup
+pub async fn backup(output: &Path) -> Result<PathBuf> {
+    for n in 1..MAX_CANDIDATES {
+        let mut backup = output.as_os_str().to_owned();
+        backup.push(format!(".~{}~", n));
+        let backup = PathBuf::from(backup);
+
+        // The empty file reserves the name and is atomically replaced by the rename
+        if create_new(&backup).await? {
+            tokio::fs::rename(output, &backup)
+                .await
+                .with_context(|| format!("Failed to back up {} to {}", output.display(), backup.display()))?;
+            return Ok(backup);
+        }
+    }
+    anyhow::bail!("No free backup name for {}", output.display())
+}
+
+/// `name (n).ext` next to `output`
+pub(crate) fn numbered(output: &Path, n: usize) -> PathBuf {
+    let mut name = output.file_stem().map(OsString::from).unwrap_or_default();
+    name.push(format!(" ({})", n));
+    if let Some(extension) = output.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    output.with_file_name(name)
+}
+
+/// Creates an empty file, returning `false` if it already exists
+async fn create_new(path: &Path) -> Result<bool> {
+    match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to create file: {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dw-conflict-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_numbered() {
+        assert_eq!(
+            numbered(Path::new("dir/file.txt"), 1),
+            Path::new("dir/file (1).txt")
+        );
+        assert_eq!(
+            numbered(Path::new("archive.tar.gz"), 2),
+            Path::new("archive.tar (2).gz")
+        );
+        assert_eq!(numbered(Path::new("README"), 3), Path::new("README (3)"));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_free_path_concurrently() {
+        let dir = temp_dir("rename");
+        let output = dir.join("file.txt");
+        std::fs::write(&output, "existing").unwrap();
+
+        let reserved = futures::future::try_join_all((0..5).map(|_| reserve_free_path(&output)))
+            .await
+            .unwrap();
+        let mut names: Vec<_> = reserved
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            names,
+            [
+                "file (1).txt",
+                "file (2).txt",
+                "file (3).txt",
+                "file (4).txt",
+                "file (5).txt"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup() {
+        let dir = temp_dir("backup");
+        let output = dir.join("file.txt");
+        std::fs::write(dir.join("file.txt.~1~"), "first").unwrap();
+        std::fs::write(&output, "second").unwrap();
+
+        let backup = backup(&output).await.unwrap();
+        let content = std::fs::read_to_string(&backup).unwrap();
+        let exists = output.exists();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(backup, dir.join("file.txt.~2~"));
+        assert_eq!(content, "second");
+        assert!(!exists);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{fmt::Display, path::PathBuf};
+
+use reqwest::StatusCode;
+
+use crate::{
+    checksum::{ChecksumMismatch, SizeMismatch},
+    retry::{IncompleteBody, StatusError},
+};
+
+/// Why a download task failed or was rejected by the builder.
+///
+/// Failures during the download keep the whole chain of the underlying error,
+/// which is shown by `Display` as `context: cause`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DownloadError {
+    /// The URL or a mirror of the task can't be parsed
+    InvalidUrl {
+        url: String,
+    },
+    /// Another task already writes to the output path
+    OutputCollision {
+        path: PathBuf,
+        used_by: String,
+        url: String,
+    },
+    /// The task has no checksum, but checksums are required
+    MissingChecksum {
+        path: PathBuf,
+        url: String,
+    },
+    /// The task downloads the same URL to the same output as an earlier task, which downloads the file
+    DuplicateTask {
+        path: PathBuf,
+        id: usize,
+    },
+    /// An entry of the input list can't be turned into a task
+    InvalidEntry(anyhow::Error),
+    /// The builder has no tasks
+    NoTasks,
+    /// The output exists and the conflict policy doesn't allow replacing it
+    FileExists {
+        path: PathBuf,
+    },
+    /// The server responded with a non-success status
+    HttpStatus {
+        status: StatusCode,
+        error: anyhow::Error,
+    },
+    /// The connection or the response timed out
+    Timeout(anyhow::Error),
+    /// Connection, protocol or decoding failure
+    Network(anyhow::Error),
+    /// The TLS handshake failed, e.g. the certificate can't be verified
+    Tls(anyhow::Error),
+    /// Reading or writing a local file failed
+    Io(anyhow::Error),
+    /// The downloaded file has a different checksum or size
+    ChecksumMismatch(anyhow::Error),
+    /// The response body is shorter or longer than announced
+    Truncated(anyhow::Error),
+    /// The download was stopped before it finished
+    Cancelled,
+    Other(anyhow::Error),
+}
+
+impl DownloadError {
+    /// Status of the response of an [`DownloadError::HttpStatus`] error
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::HttpStatus { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the error rejects the task before its download, though an output collision
+    /// is also detected during the download once the name is chosen from the response
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidUrl { .. }
+                | Self::OutputCollision { .. }
+                | Self::MissingChecksum { .. }
+                | Self::InvalidEntry(_)
+        )
+    }
+
+    /// The innermost cause without the context around it, a short message for summaries
+    pub fn root_cause(&self) -> String {
+        match self {
+            Self::InvalidEntry(error)
+            | Self::HttpStatus { error, .. }
+            | Self::Timeout(error)
+            | Self::Network(error)
+            | Self::Tls(error)
+            | Self::Io(error)
+            | Self::ChecksumMismatch(error)
+            | Self::Truncated(error)
+            | Self::Other(error) => error.root_cause().to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUrl { url } => write!(f, "Invalid URL: {}", url),
+            Self::OutputCollision { path, used_by, url } => write!(
+                f,
+                "Output path collision: {} is already used by {}, skipping {}",
+                path.display(),
+                used_by,
+                url
+            ),
+            Self::MissingChecksum { path, url } => write!(f, "No checksum for {}: {}", path.display(), url),
+            Self::DuplicateTask { path, id } => write!(f, "Duplicate of task {}: {}", id, path.display()),
+            Self::NoTasks => f.write_str("No download tasks provided"),
+            Self::FileExists { path } => write!(f, "File exists: {}", path.display()),
+            Self::InvalidEntry(error)
+            | Self::HttpStatus { error, .. }
+            | Self::Timeout(error)
+            | Self::Network(error)
+            | Self::Tls(error)
+            | Self::Io(error)
+            | Self::ChecksumMismatch(error)
+            | Self::Truncated(error)
+            | Self::Other(error) => write!(f, "{:#}", error),
+            Self::Cancelled => f.write_str("Download cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<anyhow::Error> for DownloadError {
+    /// Classifies the error by the first known cause in its chain
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<DownloadError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+
+        for cause in error.chain() {
+            if let Some(status_error) = cause.downcast_ref::<StatusError>() {
+                let status = status_error.status;
+                return Self::HttpStatus { status, error };
+            }
+            if cause.is::<ChecksumMismatch>() || cause.is::<SizeMismatch>() {
+                return Self::ChecksumMismatch(error);
+            }
+            if cause.is::<IncompleteBody>() {
+                return Self::Truncated(error);
+            }
+            if let Some(reqwest_error) = cause.downcast_ref::<reqwest::Error>() {
+                if reqwest_error.is_timeout() {
+                    return Self::Timeout(error);
+                }
+                if reqwest_error.is_connect() && is_tls_failure(reqwest_error) {
+                    return Self::Tls(error);
+                }
+                return Self::Network(error);
+            }
+            if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+                return match io_error.kind() {
+                    std::io::ErrorKind::TimedOut => Self::Timeout(error),
+                    _ => Self::Io(error),
+                };
+            }
+        }
+        Self::Other(error)
+    }
+}
+
+/// Checks the causes of the connection error for a TLS failure.
+///
+/// The TLS backends don't share an error type, so their messages are matched
+/// ("SSL routines", "invalid peer certificate", ...).
+pub(crate) fn is_tls_failure(error: &reqwest::Error) -> bool {
+    std::iter::successors(std::error::Error::source(error), |cause| cause.source()).any(|cause| {
+        let message = cause.to_string().to_lowercase();
+        ["ssl", "tls", "certificate"]
+            .iter()
+            .any(|keyword| message.contains(keyword))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+    use reqwest::header::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        let status = anyhow::Error::new(StatusError::new(
+            "https://example.com",
+            StatusCode::NOT_FOUND,
+            &HeaderMap::new(),
+        ))
+        .context("Download failed");
+        let error = DownloadError::from(status);
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(
+            error.to_string(),
+            "Download failed: Request https://example.com failed with status: 404 Not Found"
+        );
+
+        let io = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+            .context("Failed to create file: a.txt")
+            .unwrap_err();
+        assert!(matches!(DownloadError::from(io), DownloadError::Io(_)));
+
+        let exists = anyhow::Error::new(DownloadError::FileExists { path: "a.txt".into() });
+        assert!(matches!(
+            DownloadError::from(exists.context("Attempt failed")),
+            DownloadError::FileExists { .. }
+        ));
+
+        let other = DownloadError::from(anyhow::anyhow!("Unexpected Content-Range"));
+        assert!(matches!(other, DownloadError::Other(_)));
+    }
+}
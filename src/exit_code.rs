@@ -0,0 +1,268 @@
+use reqwest::StatusCode;
+
+use crate::{
+    DownloadResult,
+    error::{self, DownloadError},
+};
+
+/// Exit status of the program, modelled after wget.
+///
+/// When several tasks fail for different reasons, the lowest code wins,
+/// except for [`ExitCode::Generic`] which loses to any specific class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// An error of no specific class
+    Generic = 1,
+    /// Invalid arguments, config, input list or tasks
+    Config = 2,
+    /// Reading or writing a local file failed, or the output exists
+    Io = 3,
+    /// Connection failure, timeout or a truncated response
+    Network = 4,
+    /// The TLS handshake or the certificate verification failed
+    Tls = 5,
+    /// The server responded with `401`, `403` or `407`
+    Auth = 6,
+    /// The server responded with another error status
+    ServerError = 8,
+    /// The downloaded file has a different checksum or size
+    ChecksumMismatch = 9,
+    /// Some tasks failed, but others succeeded
+    PartialSuccess = 10,
+    /// The download was stopped by `SIGINT` or `SIGTERM`
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// Classifies the failure of a task or of the build
+    pub fn from_error(error: &DownloadError) -> Self {
+        match error {
+            DownloadError::InvalidUrl { .. }
+            | DownloadError::OutputCollision { .. }
+            | DownloadError::MissingChecksum { .. }
+            | DownloadError::DuplicateTask { .. }
+            | DownloadError::InvalidEntry(_)
+            | DownloadError::NoTasks => Self::Config,
+            DownloadError::FileExists { .. } | DownloadError::Io(_) => Self::Io,
+            DownloadError::Network(_) | DownloadError::Timeout(_) | DownloadError::Truncated(_) => {
+                Self::Network
+            }
+            DownloadError::Tls(_) => Self::Tls,
+            DownloadError::HttpStatus { status, .. } => Self::from_status(*status),
+            DownloadError::ChecksumMismatch(_) => Self::ChecksumMismatch,
+            DownloadError::Cancelled => Self::Interrupted,
+            DownloadError::Other(_) => Self::Generic,
+        }
+    }
+
+    /// Classifies an error that stopped the program before or after the downloads.
+    ///
+    /// Errors without a known cause come from the arguments, the config or the input list.
+    pub fn from_fatal(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(error) = cause.downcast_ref::<DownloadError>() {
+                return Self::from_error(error);
+            }
+            if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+                return match error.status() {
+                    Some(status) => Self::from_status(status),
+                    // The client couldn't be built from the config, e.g. because of an invalid proxy
+                    None if error.is_builder() => Self::Config,
+                    None if error.is_connect() && error::is_tls_failure(error) => Self::Tls,
+                    None => Self::Network,
+                };
+            }
+            if cause.is::<std::io::Error>() {
+                return Self::Io;
+            }
+        }
+        Self::Config
+    }
+
+    /// Returns the code of the run: [`ExitCode::Interrupted`] if any task was cancelled,
+    /// [`ExitCode::PartialSuccess`] if any task succeeded or was skipped,
+    /// otherwise the code of the most significant failure.
+    ///
+    /// Failures of the downloads take precedence over the tasks rejected by the builder,
+    /// which give [`ExitCode::Config`] only if no task was started.
+    pub fn from_result(result: &DownloadResult) -> Self {
+        if result.is_success() {
+            return Self::Success;
+        }
+        if result.cancelled().next().is_some() {
+            return Self::Interrupted;
+        }
+        if result.succeeded().next().is_some() || result.skipped().next().is_some() {
+            return Self::PartialSuccess;
+        }
+
+        let started: Vec<_> = result
+            .failed()
+            .filter(|outcome| !outcome.is_rejected())
+            .filter_map(|outcome| outcome.error.as_ref())
+            .collect();
+        let errors = if started.is_empty() {
+            result.errors()
+        } else {
+            started
+        };
+
+        errors
+            .into_iter()
+            .map(Self::from_error)
+            .min_by_key(|code| match code {
+                Self::Generic => u8::MAX,
+                code => *code as u8,
+            })
+            .unwrap_or(Self::Generic)
+    }
+
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::PROXY_AUTHENTICATION_REQUIRED => {
+                Self::Auth
+            }
+            _ => Self::ServerError,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{TaskOutcome, TaskStatus};
+
+    fn outcome(status: TaskStatus, error: Option<DownloadError>) -> TaskOutcome {
+        TaskOutcome {
+            id: 1,
+            status,
+            url: "https://example.com/a.txt".to_string(),
+            final_url: None,
+            output: "a.txt".into(),
+            http_status: None,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            checksum: None,
+            attempts: 1,
+            error,
+        }
+    }
+
+    fn http(status: StatusCode) -> DownloadError {
+        DownloadError::HttpStatus {
+            status,
+            error: anyhow::anyhow!("Request failed with status: {}", status),
+        }
+    }
+
+    #[test]
+    fn test_from_error() {
+        assert_eq!(ExitCode::from_error(&http(StatusCode::FORBIDDEN)), ExitCode::Auth);
+        assert_eq!(
+            ExitCode::from_error(&http(StatusCode::NOT_FOUND)),
+            ExitCode::ServerError
+        );
+        assert_eq!(
+            ExitCode::from_error(&DownloadError::FileExists { path: "a.txt".into() }),
+            ExitCode::Io
+        );
+        assert_eq!(ExitCode::from_error(&DownloadError::NoTasks), ExitCode::Config);
+    }
+
+    #[test]
+    fn test_from_result() {
+        let result = |outcomes: Vec<TaskOutcome>| DownloadResult {
+            total: outcomes.len(),
+            outcomes,
+        };
+
+        assert_eq!(
+            ExitCode::from_result(&result(vec![outcome(TaskStatus::Skipped, None)])),
+            ExitCode::Success
+        );
+        assert_eq!(
+            ExitCode::from_result(&result(vec![
+                outcome(
+                    TaskStatus::Failed,
+                    Some(DownloadError::Other(anyhow::anyhow!("Panic")))
+                ),
+                outcome(TaskStatus::Failed, Some(http(StatusCode::BAD_GATEWAY))),
+                outcome(TaskStatus::Failed, Some(http(StatusCode::UNAUTHORIZED))),
+            ])),
+            ExitCode::Auth
+        );
+        assert_eq!(
+            ExitCode::from_result(&result(vec![
+                outcome(TaskStatus::Succeeded, None),
+                outcome(TaskStatus::Failed, Some(http(StatusCode::NOT_FOUND))),
+            ])),
+            ExitCode::PartialSuccess
+        );
+        assert_eq!(
+            ExitCode::from_result(&result(vec![
+                outcome(TaskStatus::Succeeded, None),
+                outcome(TaskStatus::Cancelled, Some(DownloadError::Cancelled)),
+            ])),
+            ExitCode::Interrupted
+        );
+    }
+
+    #[test]
+    fn test_from_result_with_rejected_tasks() {
+        let result = |outcomes: Vec<TaskOutcome>| DownloadResult {
+            total: outcomes.len(),
+            outcomes,
+        };
+        let rejected = || TaskOutcome {
+            attempts: 0,
+            ..outcome(
+                TaskStatus::Failed,
+                Some(DownloadError::InvalidUrl { url: "a.txt".into() }),
+            )
+        };
+
+        assert_eq!(
+            ExitCode::from_result(&result(vec![rejected(), rejected()])),
+            ExitCode::Config
+        );
+        assert_eq!(
+            ExitCode::from_result(&result(vec![outcome(TaskStatus::Succeeded, None), rejected()])),
+            ExitCode::PartialSuccess
+        );
+        assert_eq!(
+            ExitCode::from_result(&result(vec![
+                rejected(),
+                outcome(TaskStatus::Failed, Some(http(StatusCode::NOT_FOUND))),
+            ])),
+            ExitCode::ServerError
+        );
+    }
+
+    #[test]
+    fn test_from_fatal() {
+        let io = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::NotFound))
+            .context("Failed to open source file");
+        assert_eq!(ExitCode::from_fatal(&io), ExitCode::Io);
+        assert_eq!(
+            ExitCode::from_fatal(&anyhow::anyhow!("Invalid header")),
+            ExitCode::Config
+        );
+        assert_eq!(
+            ExitCode::from_fatal(&DownloadError::NoTasks.into()),
+            ExitCode::Config
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::Path;
+
+use reqwest::{
+    Response, Url,
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+};
+
+/// Longest file name most file systems accept, in bytes
+const MAX_FILENAME_BYTES: usize = 255;
+
+/// Chooses the name of the downloaded file after the response arrives.
+///
+/// Prefers the `Content-Disposition` name, then the last segment of the URL after redirects,
+/// then `fallback`. Names that aren't plain file names are skipped. An extension inferred from
+/// `Content-Type` is appended to a name without one.
+pub fn from_response(response: &Response, fallback: &str) -> String {
+    let headers = response.headers();
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| std::str::from_utf8(value.as_bytes()).ok())
+    };
+
+    let mut name = header(CONTENT_DISPOSITION)
+        .and_then(parse_content_disposition)
+        .filter(|name| is_safe_filename(name))
+        .or_else(|| url_filename(response.url()).filter(|name| is_safe_filename(name)))
+        .unwrap_or_else(|| fallback.to_string());
+
+    if Path::new(&name).extension().is_none()
+        && let Some(extension) = header(CONTENT_TYPE).and_then(extension_for)
+    {
+        name = format!("{}.{}", name, extension);
+    }
+    name
+}
+
+/// Extracts the file name from a `Content-Disposition` header (RFC 6266).
+///
+/// `filename*` with an UTF-8 or ISO-8859-1 encoded value takes precedence over `filename`.
+pub fn parse_content_disposition(header: &str) -> Option<String> {
+    let mut filename = None;
+    let mut extended = None;
+
+    for (name, value) in parameters(header) {
+        match name.as_str() {
+            "filename*" if extended.is_none() => extended = decode_ext_value(&value),
+            "filename" if filename.is_none() => filename = Some(value),
+            _ => {}
+        }
+    }
+    extended.or(filename)
+}
+
+/// Checks that a name supplied by the server is a plain file name, rejecting paths and traversal
+pub fn is_safe_filename(name: &str) -> bool {
+    !name.trim().is_empty()
+        && name != "."
+        && name != ".."
+        && name.len() <= MAX_FILENAME_BYTES
+        && !name
+            .chars()
+            .any(|c| c.is_control() || matches!(c, '/' | '\\' | ':'))
+}
+
+/// Extension of the files with the MIME type, `None` for unknown and generic binary types
+pub fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+
+    Some(match mime.as_str() {
+        "text/html" => "html",
+        "text/plain" => "txt",
+        "text/css" => "css",
+        "text/csv" => "csv",
+        "text/javascript" | "application/javascript" => "js",
+        "text/xml" | "application/xml" => "xml",
+        "application/json" => "json",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/gzip" | "application/x-gzip" => "gz",
+        "application/x-tar" => "tar",
+        "application/x-bzip2" => "bz2",
+        "application/x-xz" => "xz",
+        "application/x-7z-compressed" => "7z",
+        "application/wasm" => "wasm",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        _ => return None,
+    })
+}
+
+/// The decoded last segment of the URL path, `None` if the path ends with `/`
+fn url_filename(url: &Url) -> Option<String> {
+    let segment = url
+        .path_segments()?
+        .next_back()
+        .filter(|segment| !segment.is_empty())?;
+    String::from_utf8(percent_decode(segment)).ok()
+}
+
+/// Splits the parameters after the disposition type into lowercase names and unquoted values
+fn parameters(header: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let Some((_, mut rest)) = header.split_once(';') else {
+        return parameters;
+    };
+
+    while let Some((name, value)) = rest.split_once('=') {
+        let value = value.trim_start();
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut unquoted = String::new();
+                let mut end = quoted.len();
+                let mut chars = quoted.char_indices();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => unquoted.push(c),
+                    }
+                }
+                (unquoted, &quoted[end..])
+            }
+            None => {
+                let end = value.find(';').unwrap_or(value.len());
+                (value[..end].trim().to_string(), &value[end..])
+            }
+        };
+        parameters.push((name.trim().to_ascii_lowercase(), value));
+
+        match tail.split_once(';') {
+            Some((_, next)) => rest = next,
+            None => break,
+        }
+    }
+    parameters
+}
+
+/// Decodes an RFC 8187 value: `charset'language'percent-encoded`
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes = percent_decode(encoded);
+
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| hex::decode(hex).ok())
+        {
+            Some(byte) => {
+                decoded.extend(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_disposition() {
+        let cases = [
+            ("attachment; filename=report.pdf", Some("report.pdf")),
+            (
+                "attachment; filename=\"annual report.pdf\"",
+                Some("annual report.pdf"),
+            ),
+            ("attachment; filename=\"a\\\"b.txt\"; size=10", Some("a\"b.txt")),
+            (
+                "ATTACHMENT; FILENAME = \"semi;colon.txt\"",
+                Some("semi;colon.txt"),
+            ),
+            (
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''%D0%BE%D1%82%D1%87%D1%91%D1%82.txt",
+                Some("отчёт.txt"),
+            ),
+            (
+                "attachment; filename*=iso-8859-1'en'%A3%20rates.txt",
+                Some("£ rates.txt"),
+            ),
+            (
+                "attachment; filename*=koi8-r''%C1.txt; filename=plain.txt",
+                Some("plain.txt"),
+            ),
+            ("inline", None),
+            ("attachment; size=10", None),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(
+                parse_content_disposition(header).as_deref(),
+                expected,
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn test_safe_filename() {
+        for name in ["file.txt", "..file", ".hidden", "отчёт.txt"] {
+            assert!(is_safe_filename(name), "{}", name);
+        }
+        for name in [
+            "",
+            " ",
+            ".",
+            "..",
+            "../etc/passwd",
+            "/etc/passwd",
+            "..\\boot.ini",
+            "C:evil",
+            "a\nb",
+        ] {
+            assert!(!is_safe_filename(name), "{:?}", name);
+        }
+        assert!(!is_safe_filename(&"a".repeat(256)));
+    }
+
+    #[test]
+    fn test_extension_for() {
+        assert_eq!(extension_for("text/html; charset=utf-8"), Some("html"));
+        assert_eq!(extension_for("Application/PDF"), Some("pdf"));
+        assert_eq!(extension_for("application/octet-stream"), None);
+    }
+
+    #[test]
+    fn test_url_filename() {
+        let url = |url| url_filename(&Url::parse(url).unwrap());
+
+        assert_eq!(
+            url("https://example.com/files/a%20b.zip?x=1").as_deref(),
+            Some("a b.zip")
+        );
+        assert_eq!(url("https://example.com/files/"), None);
+        assert_eq!(url("https://example.com/a%2F..%2Fb").as_deref(), Some("a/../b"));
+    }
+}
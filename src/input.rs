@@ -0,0 +1,136 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use tokio::sync::Mutex;
+
+use crate::{DownloadTask, checksum::Checksum, conflict::ConflictPolicy, reporter::DownloadReporter};
+
+pub mod list;
+pub mod manifest;
+
+/// Format of a file with the list of downloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputFormat {
+    /// URL per line with aria2-style options
+    List,
+    /// JSON array of entries
+    Json,
+    /// JSON entry per line
+    Jsonl,
+    /// CSV with a header row
+    Csv,
+}
+
+impl InputFormat {
+    /// Detects the format by the file extension, falling back to the list
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("json") => Self::Json,
+            Some("jsonl" | "ndjson") => Self::Jsonl,
+            Some("csv") => Self::Csv,
+            _ => Self::List,
+        }
+    }
+
+    /// Parses the content in the format. Invalid entries are skipped and returned as errors
+    pub fn parse(self, content: &str) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+        match self {
+            Self::List => list::parse(content),
+            Self::Json => manifest::parse_json(content),
+            Self::Jsonl => manifest::parse_json_lines(content),
+            Self::Csv => manifest::parse_csv(content),
+        }
+    }
+}
+
+/// Checks that a path of an input entry stays inside the destination directory:
+/// absolute paths and `..` components are rejected
+pub fn relative_path(path: impl Into<PathBuf>) -> Result<PathBuf> {
+    let path = path.into();
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        anyhow::bail!(
+            "Path '{}' must be relative to the destination directory and can't contain '..'",
+            path.display()
+        );
+    }
+    Ok(path)
+}
+
+/// A download described by an input file
+#[derive(Debug, Default, Clone)]
+pub struct InputEntry {
+    /// The URL followed by its mirrors, tried in order
+    pub urls: Vec<String>,
+    /// Name of the output file, derived from the URL if not set
+    pub output: Option<PathBuf>,
+    /// Directory of the output file, relative to the destination directory
+    pub dir: Option<PathBuf>,
+    pub checksum: Option<Checksum>,
+    pub headers: HeaderMap,
+    /// Speed limit in bytes per second
+    pub rate_limit: Option<u64>,
+    /// Overwrites the existing file if `true`, fails if `false`, otherwise the policy of the run is used
+    pub overwrite: Option<bool>,
+    /// Expected size of the file in bytes
+    pub size: Option<u64>,
+}
+
+impl InputEntry {
+    pub fn new(url: &str) -> Self {
+        Self {
+            urls: vec![url.to_string()],
+            ..Default::default()
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.urls[0]
+    }
+
+    /// Output path of the entry inside the `destination` directory
+    pub fn output_path(&self, destination: &Path) -> PathBuf {
+        let dir = match &self.dir {
+            Some(dir) => destination.join(dir),
+            None => destination.to_path_buf(),
+        };
+        let name = match &self.output {
+            Some(output) => output.clone(),
+            None => DownloadTask::sanitize_filename(self.url()).into(),
+        };
+        dir.join(name)
+    }
+
+    pub fn into_task(
+        self,
+        destination: &Path,
+        on_conflict: ConflictPolicy,
+        reporter: Arc<Mutex<dyn DownloadReporter>>,
+    ) -> DownloadTask {
+        let on_conflict = match self.overwrite {
+            Some(true) => ConflictPolicy::Overwrite,
+            Some(false) => ConflictPolicy::Fail,
+            None => on_conflict,
+        };
+        let mut task = DownloadTask::new(self.url(), self.output_path(destination), on_conflict, reporter)
+            .with_mirrors(self.urls[1..].to_vec())
+            .with_checksum(self.checksum)
+            .with_headers(self.headers)
+            .with_size(self.size);
+        if let Some(rate_limit) = self.rate_limit {
+            task = task.with_rate_limit(rate_limit);
+        }
+        task
+    }
+}
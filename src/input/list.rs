@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+
+use super::{InputEntry, relative_path};
+use crate::{
+    Downloader, auth,
+    checksum::{Checksum, ChecksumAlgorithm},
+    rate_limit,
+};
+
+/// Parses a list of URLs in the aria2 input file format:
+///
+/// ```text
+/// # Comment
+/// https://example.com/file.iso https://mirror.example.com/file.iso
+///   out=image.iso
+///   dir=images
+///   checksum=sha-256=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///   header=Authorization: Bearer <token>
+///   max-download-limit=2M
+/// https://example.com/readme.txt sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+/// ```
+///
+/// A URL line starts an entry and may list mirrors and a checksum after the URL, the indented lines
+/// below it are options of the entry. Invalid entries, including ones with an invalid URL or mirror,
+/// are skipped and returned as errors with their line numbers, one error per entry.
+pub fn parse(content: &str) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    // The entry being parsed, its line and the errors of its options
+    let mut current: Option<(InputEntry, usize, Vec<anyhow::Error>)> = None;
+
+    for (line_num, line) in content.lines().enumerate() {
+        let line_num = line_num + 1;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            match current.as_mut() {
+                Some((entry, _, entry_errors)) => {
+                    if let Err(err) = parse_option(entry, line.trim()) {
+                        entry_errors.push(err.context(format!("Invalid option on line {}", line_num)));
+                    }
+                }
+                None => errors.push(anyhow::anyhow!("Option without a URL on line {}", line_num)),
+            }
+            continue;
+        }
+
+        finish_entry(current.take(), &mut entries, &mut errors);
+        match parse_urls(line) {
+            Ok(entry) => current = Some((entry, line_num, Vec::new())),
+            Err(err) => errors.push(err.context(format!("Invalid entry on line {}", line_num))),
+        }
+    }
+    finish_entry(current, &mut entries, &mut errors);
+
+    (entries, errors)
+}
+
+/// Keeps the entry if none of its options failed, otherwise combines the errors of its options
+fn finish_entry(
+    entry: Option<(InputEntry, usize, Vec<anyhow::Error>)>,
+    entries: &mut Vec<InputEntry>,
+    errors: &mut Vec<anyhow::Error>,
+) {
+    let Some((entry, line_num, mut entry_errors)) = entry else {
+        return;
+    };
+    match entry_errors.len() {
+        0 => entries.push(entry),
+        1 => errors.append(&mut entry_errors),
+        _ => {
+            let messages: Vec<_> = entry_errors.iter().map(|err| format!("{:#}", err)).collect();
+            errors.push(
+                anyhow::anyhow!("{}", messages.join("; "))
+                    .context(format!("Invalid entry on line {}", line_num)),
+            );
+        }
+    }
+}
+
+/// Parses `<url> [<mirror>...] [<algorithm>:<hex>]`
+fn parse_urls(line: &str) -> Result<InputEntry> {
+    let mut columns = line.split_whitespace();
+    let mut entry = InputEntry::new(parse_url(columns.next().unwrap_or_default())?);
+
+    for column in columns {
+        let is_checksum = column
+            .split_once(':')
+            .is_some_and(|(algorithm, _)| algorithm.parse::<ChecksumAlgorithm>().is_ok());
+
+        if is_checksum {
+            entry.checksum = Some(column.parse()?);
+        } else {
+            entry.urls.push(parse_url(column)?.to_string());
+        }
+    }
+
+    Ok(entry)
+}
+
+/// Checks the URL of a column, so that a line of plain text isn't taken for a URL with mirrors
+fn parse_url(column: &str) -> Result<&str> {
+    if !Downloader::is_valid_url(column) {
+        anyhow::bail!("Invalid URL '{}'", auth::redact_url(column));
+    }
+    Ok(column)
+}
+
+/// Parses a `name=value` option
+fn parse_option(entry: &mut InputEntry, option: &str) -> Result<()> {
+    let (name, value) = option
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected 'name=value'"))?;
+
+    match name.trim() {
+        "out" => entry.output = Some(relative_path(value)?),
+        "dir" => entry.dir = Some(relative_path(value)?),
+        "checksum" => entry.checksum = Some(parse_checksum(value)?),
+        "header" => {
+            let (name, value) = auth::parse_header(value)?;
+            entry.headers.append(name, value);
+        }
+        "max-download-limit" => entry.rate_limit = Some(rate_limit::parse_rate(value)?),
+        name => anyhow::bail!("Unknown option '{}'", name),
+    }
+    Ok(())
+}
+
+/// Accepts both the aria2 `sha-256=<hex>` and the `sha256:<hex>` forms
+fn parse_checksum(checksum: &str) -> Result<Checksum> {
+    let (algorithm, digest) = checksum
+        .split_once(['=', ':'])
+        .ok_or_else(|| anyhow::anyhow!("Invalid checksum '{}', expected <algorithm>=<hex>", checksum))?;
+
+    Checksum::new(algorithm.parse()?, digest).context("Invalid checksum")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn test_parse() {
+        let content = format!(
+            "# Comment\n\
+            https://example.com/file.iso\thttps://mirror.example.com/file.iso\n\
+            \x20 out=image.iso\n\
+            \x20 dir=images\n\
+            \tchecksum=sha-256={HASH}\n\
+            \x20 header=X-Token: secret\n\
+            \x20 header=Accept: */*\n\
+            \x20 max-download-limit=2M\n\
+            \n\
+            https://example.com/readme.txt sha256:{HASH}\n"
+        );
+        let (entries, errors) = parse(&content);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(entries.len(), 2);
+
+        let entry = &entries[0];
+        assert_eq!(
+            entry.urls,
+            [
+                "https://example.com/file.iso",
+                "https://mirror.example.com/file.iso"
+            ]
+        );
+        assert_eq!(
+            entry.output_path(Path::new("/downloads")),
+            PathBuf::from("/downloads/images/image.iso")
+        );
+        assert_eq!(
+            entry.checksum.as_ref().unwrap().algorithm,
+            ChecksumAlgorithm::Sha256
+        );
+        assert_eq!(entry.headers["x-token"], "secret");
+        assert_eq!(entry.headers.len(), 2);
+        assert_eq!(entry.rate_limit, Some(2 * 1024 * 1024));
+
+        let entry = &entries[1];
+        assert_eq!(entry.checksum.as_ref().unwrap().hex(), HASH);
+        assert_eq!(
+            entry.output_path(Path::new("/downloads")),
+            PathBuf::from("/downloads/readme.txt")
+        );
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let content = "  out=orphan\n\
+            https://example.com/a.txt\n\
+            \x20 unknown=1\n\
+            \x20 header=X-Token secret\n\
+            https://example.com/b.txt md5:123\n\
+            https://example.com/c.txt\n\
+            \x20 out=c.txt\n\
+            not a url\n\
+            https://example.com/d.txt not-a-mirror\n\
+            https://example.com/e.txt\n\
+            \x20 out=../e.txt\n\
+            https://example.com/f.txt\n\
+            \x20 dir=/etc\n";
+        let (entries, errors) = parse(content);
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url(), "https://example.com/c.txt");
+
+        let errors: Vec<_> = errors.iter().map(|err| format!("{:#}", err)).collect();
+        assert_eq!(errors.len(), 7, "{:#?}", errors);
+        assert!(errors[0].contains("line 1"), "{}", errors[0]);
+        // Both invalid options of the entry are reported as one invalid entry
+        assert!(
+            errors[1].starts_with("Invalid entry on line 2")
+                && errors[1].contains("line 3")
+                && errors[1].contains("unknown")
+                && errors[1].contains("line 4")
+                && !errors[1].contains("secret"),
+            "{}",
+            errors[1]
+        );
+        assert!(errors[2].contains("line 5"), "{}", errors[2]);
+        assert!(
+            errors[3].contains("line 8") && errors[3].contains("'not'"),
+            "{}",
+            errors[3]
+        );
+        assert!(
+            errors[4].contains("line 9") && errors[4].contains("not-a-mirror"),
+            "{}",
+            errors[4]
+        );
+        assert!(
+            errors[5].contains("line 11") && errors[5].contains("../e.txt"),
+            "{}",
+            errors[5]
+        );
+        assert!(
+            errors[6].contains("line 13") && errors[6].contains("/etc"),
+            "{}",
+            errors[6]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::{InputEntry, relative_path};
+use crate::{
+    auth,
+    checksum::{Checksum, ChecksumAlgorithm},
+};
+
+/// An entry of a JSON manifest:
+///
+/// ```json
+/// {
+///     "url": "https://example.com/file.iso",
+///     "output": "images/file.iso",
+///     "overwrite": true,
+///     "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+///     "headers": { "Authorization": "Bearer <token>" },
+///     "size": 1048576,
+///     "mirrors": ["https://mirror.example.com/file.iso"]
+/// }
+/// ```
+///
+/// Only `url` is required.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEntry {
+    url: String,
+    #[serde(default)]
+    output: Option<PathBuf>,
+    #[serde(default)]
+    overwrite: Option<bool>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    mirrors: Vec<String>,
+}
+
+/// A row of a CSV manifest with the same columns as [`JsonEntry`].
+///
+/// `mirrors` are separated by whitespace and `headers` by newlines (`Name: value` each).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsvEntry {
+    url: String,
+    #[serde(default)]
+    output: Option<PathBuf>,
+    #[serde(default)]
+    overwrite: Option<bool>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    headers: Option<String>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    mirrors: Option<String>,
+}
+
+/// Parses a JSON array of entries. Invalid entries are skipped and returned as errors with their index
+pub fn parse_json(content: &str) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+    let values: Vec<serde_json::Value> = match serde_json::from_str(content) {
+        Ok(values) => values,
+        Err(err) => {
+            return (
+                Vec::new(),
+                vec![anyhow::anyhow!("Invalid JSON manifest: {}", err)],
+            );
+        }
+    };
+
+    collect(values.into_iter().enumerate().map(|(index, value)| {
+        serde_json::from_value::<JsonEntry>(value)
+            .map_err(anyhow::Error::from)
+            .and_then(JsonEntry::into_entry)
+            .with_context(|| format!("Invalid entry at index {}", index))
+    }))
+}
+
+/// Parses one JSON entry per line, empty lines are ignored
+pub fn parse_json_lines(content: &str) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+    collect(
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(line_num, line)| {
+                serde_json::from_str::<JsonEntry>(line)
+                    .map_err(anyhow::Error::from)
+                    .and_then(JsonEntry::into_entry)
+                    .with_context(|| format!("Invalid entry on line {}", line_num + 1))
+            }),
+    )
+}
+
+/// Parses a CSV file with a header row, columns may go in any order
+pub fn parse_csv(content: &str) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => return (Vec::new(), vec![anyhow::anyhow!("Invalid CSV manifest: {}", err)]),
+    };
+
+    collect(reader.into_records().map(|record| {
+        let line = match &record {
+            Ok(record) => record.position(),
+            Err(err) => err.position(),
+        }
+        .map_or(0, csv::Position::line);
+
+        record
+            .and_then(|record| record.deserialize::<CsvEntry>(Some(&headers)))
+            .map_err(anyhow::Error::from)
+            .and_then(CsvEntry::into_entry)
+            .with_context(|| format!("Invalid entry on line {}", line))
+    }))
+}
+
+/// Splits the results into the entries and the errors
+fn collect(results: impl Iterator<Item = Result<InputEntry>>) -> (Vec<InputEntry>, Vec<anyhow::Error>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for result in results {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(err) => errors.push(err),
+        }
+    }
+    (entries, errors)
+}
+
+impl JsonEntry {
+    fn into_entry(self) -> Result<InputEntry> {
+        let mut entry = InputEntry::new(&self.url);
+        entry.urls.extend(self.mirrors);
+        entry.output = self.output.map(relative_path).transpose()?;
+        entry.overwrite = self.overwrite;
+        entry.size = self.size;
+        entry.checksum = self.sha256.as_deref().map(parse_sha256).transpose()?;
+
+        for (name, value) in &self.headers {
+            let (name, value) = auth::parse_header_pair(name, value)?;
+            entry.headers.append(name, value);
+        }
+        Ok(entry)
+    }
+}
+
+impl CsvEntry {
+    fn into_entry(self) -> Result<InputEntry> {
+        let mut entry = InputEntry::new(&self.url);
+        if let Some(mirrors) = &self.mirrors {
+            entry.urls.extend(mirrors.split_whitespace().map(str::to_string));
+        }
+        entry.output = self.output.map(relative_path).transpose()?;
+        entry.overwrite = self.overwrite;
+        entry.size = self.size;
+        entry.checksum = self.sha256.as_deref().map(parse_sha256).transpose()?;
+
+        for header in self.headers.iter().flat_map(|headers| headers.lines()) {
+            if !header.trim().is_empty() {
+                let (name, value) = auth::parse_header(header)?;
+                entry.headers.append(name, value);
+            }
+        }
+        Ok(entry)
+    }
+}
+
+fn parse_sha256(digest: &str) -> Result<Checksum> {
+    Checksum::new(ChecksumAlgorithm::Sha256, digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn test_parse_json() {
+        let content = format!(
+            r#"[
+                {{
+                    "url": "https://example.com/file.iso",
+                    "output": "images/file.iso",
+                    "overwrite": true,
+                    "sha256": "{HASH}",
+                    "headers": {{ "Authorization": "Bearer secret" }},
+                    "size": 1024,
+                    "mirrors": ["https://mirror.example.com/file.iso"]
+                }},
+                {{ "url": "https://example.com/a.txt", "sha256": "123" }},
+                {{ "url": "https://example.com/b.txt", "unknown": 1 }},
+                {{ "output": "c.txt" }},
+                {{ "url": "https://example.com/d.txt", "headers": {{ "X-Token": "secret\u0001" }} }},
+                {{ "url": "https://example.com/e.txt" }}
+            ]"#
+        );
+        let (entries, errors) = parse_json(&content);
+
+        assert_eq!(entries.len(), 2);
+        let entry = &entries[0];
+        assert_eq!(
+            entry.urls,
+            [
+                "https://example.com/file.iso",
+                "https://mirror.example.com/file.iso"
+            ]
+        );
+        assert_eq!(entry.output, Some("images/file.iso".into()));
+        assert_eq!(entry.overwrite, Some(true));
+        assert_eq!(entry.checksum.as_ref().unwrap().hex(), HASH);
+        assert!(entry.headers["authorization"].is_sensitive());
+        assert_eq!(entry.size, Some(1024));
+        assert_eq!(entries[1].url(), "https://example.com/e.txt");
+
+        let errors: Vec<_> = errors.iter().map(|err| format!("{:#}", err)).collect();
+        assert_eq!(errors.len(), 4, "{:#?}", errors);
+        for (error, index) in errors.iter().zip(1..) {
+            assert!(
+                error.starts_with(&format!("Invalid entry at index {}", index)),
+                "{}",
+                error
+            );
+            assert!(!error.contains("secret"), "{}", error);
+        }
+
+        let (entries, errors) = parse_json("{ \"url\": \"https://example.com\" }");
+        assert!(entries.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_json_lines() {
+        let content = "{\"url\": \"https://example.com/a.txt\", \"output\": \"b.txt\"}\n\
+            \n\
+            {\"url\": \"https://example.com/c.txt\", \"size\": -1}\n\
+            {\"url\": \"https://example.com/d.txt\"}\n\
+            {\"url\": \"https://example.com/e.txt\", \"output\": \"/etc/e.txt\"}\n\
+            {\"url\": \"https://example.com/f.txt\", \"output\": \"sub/../../f.txt\"}\n";
+        let (entries, errors) = parse_json_lines(content);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].output, Some("b.txt".into()));
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].to_string().contains("line 3"), "{}", errors[0]);
+        for (error, line) in errors[1..].iter().zip([5, 6]) {
+            let error = format!("{:#}", error);
+            assert!(
+                error.contains(&format!("line {}", line)) && error.contains("must be relative"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let content = format!(
+            "url,output,sha256,size,overwrite,mirrors,headers\n\
+            https://example.com/a.txt, a.txt, {HASH}, 10, false, https://m1.com/a.txt https://m2.com/a.txt,\"X-A: 1\nX-B: 2\"\n\
+            https://example.com/b.txt,,,,,,\n\
+            https://example.com/c.txt,,,ten,,,\n\
+            https://example.com/d.txt,,,,,,X-Token secret\n"
+        );
+        let (entries, errors) = parse_csv(&content);
+
+        assert_eq!(entries.len(), 2, "{:#?}", errors);
+        let entry = &entries[0];
+        assert_eq!(entry.urls.len(), 3);
+        assert_eq!(entry.output, Some("a.txt".into()));
+        assert_eq!(entry.checksum.as_ref().unwrap().hex(), HASH);
+        assert_eq!(entry.size, Some(10));
+        assert_eq!(entry.overwrite, Some(false));
+        assert_eq!(entry.headers.len(), 2);
+
+        let entry = &entries[1];
+        assert_eq!(entry.urls, ["https://example.com/b.txt"]);
+        assert_eq!(entry.output, None);
+        assert_eq!(entry.checksum, None);
+
+        let errors: Vec<_> = errors.iter().map(|err| format!("{:#}", err)).collect();
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        assert!(errors[0].starts_with("Invalid entry on line 5"), "{}", errors[0]);
+        assert!(!errors[1].contains("secret"), "{}", errors[1]);
+    }
+}
@@ -1,56 +1,348 @@
 use anyhow::{Context, Result};
 use futures::StreamExt;
 use regex::Regex;
-use reqwest::{self, Client, Response};
+use reqwest::{
+    self, Client, Response, StatusCode,
+    header::{self, HeaderMap},
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
 use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 
+use auth::netrc::Netrc;
 use builder::DownloaderBuilder;
-use config::app::MAX_PARALLELS_REQUESTS;
+use checksum::{Checksum, ChecksumMismatch, Hasher, SizeMismatch};
+use config::app::{MAX_PARALLELS_REQUESTS, MIN_SEGMENT_SIZE, SEGMENTS};
+use conflict::{CollisionPolicy, ConflictPolicy, OutputPaths};
+use error::DownloadError;
+use rate_limit::RateLimiter;
 use reporter::DownloadReporter;
+use resume::ResumeState;
+use retry::{IncompleteBody, RetryPolicy, StatusError};
 
+pub mod auth;
 pub mod builder;
+pub mod checksum;
 pub mod config;
+pub mod conflict;
+pub mod error;
+pub mod exit_code;
+pub mod filename;
+pub mod input;
+pub mod rate_limit;
+pub mod report;
 pub mod reporter;
+pub mod resume;
+pub mod retry;
+pub mod segmented;
+
+/// Suffix of the file with an incomplete download
+const PART_SUFFIX: &str = ".part";
 
 pub struct Downloader {
     tasks: Vec<DownloadTask>,
+    /// Number of the next added task
+    next_id: usize,
     client: Client,
     parallel_requests: Arc<Semaphore>,
+    settings: TaskSettings,
+}
+
+/// Settings shared by all download tasks
+#[derive(Debug, Clone)]
+struct TaskSettings {
+    retry_policy: RetryPolicy,
+    /// Maximum number of connections per file
+    segments: usize,
+    min_segment_size: u64,
+    /// Headers of every request, overridden by the task headers
+    headers: HeaderMap,
+    netrc: Option<Arc<Netrc>>,
+    /// Limits the aggregate speed of all tasks
+    rate_limiter: RateLimiter,
+    /// Resolves the collisions of the output paths chosen from the responses
+    collision_policy: CollisionPolicy,
+    outputs: Arc<Mutex<OutputPaths>>,
+    /// Stops starting new tasks and interrupts the running ones
+    cancellation: CancellationToken,
+}
+
+impl TaskSettings {
+    /// Awaits the future, failing with [`DownloadError::Cancelled`] if the download is cancelled first
+    async fn until_cancelled<F: Future>(&self, future: F) -> Result<F::Output> {
+        self.cancellation
+            .run_until_cancelled(future)
+            .await
+            .ok_or_else(|| DownloadError::Cancelled.into())
+    }
+}
+
+impl Default for TaskSettings {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            segments: SEGMENTS,
+            min_segment_size: MIN_SEGMENT_SIZE,
+            headers: HeaderMap::new(),
+            netrc: None,
+            rate_limiter: RateLimiter::default(),
+            collision_policy: CollisionPolicy::default(),
+            outputs: Arc::default(),
+            cancellation: CancellationToken::new(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DownloadTask {
+    /// Number of the task in the order it was added, from 1, assigned by the builder or the downloader
+    pub(crate) id: usize,
+    /// The URL being downloaded, replaced by the next mirror when it fails
     pub url: String,
+    /// Fallback URLs of the same file, tried in order after `url` fails
+    pub mirrors: Vec<String>,
     pub output: PathBuf,
-    pub overwrite: bool,
+    /// What to do if the output file already exists
+    pub on_conflict: ConflictPolicy,
     pub reporter: Arc<Mutex<dyn DownloadReporter>>, // TODO: Wrap a parameter in a Option<T>
+    /// Expected digest of the downloaded file
+    pub checksum: Option<Checksum>,
+    /// Request headers, replacing the common headers of the downloader with the same name
+    pub headers: HeaderMap,
+    /// Speed limit of the task, in addition to the limit of the downloader
+    pub rate_limiter: Option<RateLimiter>,
+    /// Expected size of the file in bytes
+    pub size: Option<u64>,
+    /// The file name is chosen from the response, `output` only gives the directory and the fallback name
+    pub name_from_response: bool,
+    /// The empty file created by the task to reserve its name under [`ConflictPolicy::Rename`]
+    pub(crate) reserved_output: Option<PathBuf>,
+    /// The `.part` file was written by this run from the current URL, so a retry may continue it
+    pub(crate) part_written: bool,
 }
 
 #[derive(Debug)]
 pub struct DownloadResult {
     pub total: usize,
-    pub errors: Vec<anyhow::Error>,
+    /// Outcomes of the tasks in the order they were added to the downloader
+    pub outcomes: Vec<TaskOutcome>,
 }
 
 impl DownloadResult {
     fn new(total: usize) -> Self {
         Self {
             total,
-            errors: Vec::new(),
+            outcomes: Vec::new(),
+        }
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = &TaskOutcome> {
+        self.with_status(TaskStatus::Succeeded)
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &TaskOutcome> {
+        self.with_status(TaskStatus::Skipped)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &TaskOutcome> {
+        self.with_status(TaskStatus::Failed)
+    }
+
+    pub fn cancelled(&self) -> impl Iterator<Item = &TaskOutcome> {
+        self.with_status(TaskStatus::Cancelled)
+    }
+
+    /// Adds the outcomes of the tasks rejected by [`DownloaderBuilder::build`], keeping the task order
+    pub fn add_rejected(&mut self, rejected: Vec<TaskOutcome>) {
+        self.total += rejected.len();
+        self.outcomes.extend(rejected);
+        self.outcomes.sort_by_key(|outcome| outcome.id);
+    }
+
+    /// Errors of the failed tasks in the task order
+    pub fn errors(&self) -> Vec<&DownloadError> {
+        self.failed()
+            .filter_map(|outcome| outcome.error.as_ref())
+            .collect()
+    }
+
+    /// Checks that every task either succeeded or was skipped
+    pub fn is_success(&self) -> bool {
+        self.outcomes
+            .iter()
+            .all(|outcome| matches!(outcome.status, TaskStatus::Succeeded | TaskStatus::Skipped))
+    }
+
+    fn with_status(&self, status: TaskStatus) -> impl Iterator<Item = &TaskOutcome> {
+        self.outcomes
+            .iter()
+            .filter(move |outcome| outcome.status == status)
+    }
+}
+
+/// How a download task ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Succeeded,
+    /// The output exists and the conflict policy keeps it, or an earlier task downloads the same file
+    Skipped,
+    Failed,
+    /// The run was stopped before the task finished
+    Cancelled,
+}
+
+impl TaskStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What happened to a download task
+#[derive(Debug)]
+pub struct TaskOutcome {
+    /// Number of the task in the order it was added, from 1, the same as `task` of the JSON events
+    pub id: usize,
+    pub status: TaskStatus,
+    /// URL of the task without credentials
+    pub url: String,
+    /// URL of the last response after redirects and mirror fallbacks, without credentials
+    pub final_url: Option<String>,
+    pub output: PathBuf,
+    /// Status of the last response
+    pub http_status: Option<StatusCode>,
+    /// Size of the downloaded file, or of the partial file if the download failed
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// The checksum the file was verified with
+    pub checksum: Option<Checksum>,
+    /// Number of attempts, including retries and mirrors
+    pub attempts: usize,
+    /// The error of a failed task, or why the builder skipped the task
+    pub error: Option<DownloadError>,
+}
+
+impl TaskOutcome {
+    /// The outcome of a task that hasn't finished yet
+    fn new(task: &DownloadTask) -> Self {
+        Self::with_task(task.id, task.redacted_url().into_owned(), task.output.clone())
+    }
+
+    /// The outcome of an input entry that couldn't be turned into a task
+    fn invalid_entry(id: usize, error: anyhow::Error) -> Self {
+        Self::with_task(id, String::new(), PathBuf::new()).fail(DownloadError::InvalidEntry(error))
+    }
+
+    fn with_task(id: usize, url: String, output: PathBuf) -> Self {
+        Self {
+            id,
+            status: TaskStatus::Failed,
+            url,
+            final_url: None,
+            output,
+            http_status: None,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            checksum: None,
+            attempts: 0,
+            error: None,
+        }
+    }
+
+    fn fail(mut self, error: DownloadError) -> Self {
+        self.status = match error {
+            DownloadError::Cancelled => TaskStatus::Cancelled,
+            _ => TaskStatus::Failed,
+        };
+        self.error = Some(error);
+        self
+    }
+
+    fn skip(mut self, reason: DownloadError) -> Self {
+        self.status = TaskStatus::Skipped;
+        self.error = Some(reason);
+        self
+    }
+
+    /// Checks whether the task was rejected by the builder and never started
+    pub fn is_rejected(&self) -> bool {
+        self.attempts == 0 && self.error.as_ref().is_some_and(DownloadError::is_rejection)
+    }
+
+    /// Average speed in bytes per second
+    pub fn speed(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => self.bytes as f64 / secs,
         }
     }
 }
 
+/// A single URL or an ordered list of mirrors of the same file
+pub trait IntoUrls {
+    fn into_urls(self) -> Vec<String>;
+}
+
+impl IntoUrls for &str {
+    fn into_urls(self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl IntoUrls for &String {
+    fn into_urls(self) -> Vec<String> {
+        vec![self.clone()]
+    }
+}
+
+impl IntoUrls for String {
+    fn into_urls(self) -> Vec<String> {
+        vec![self]
+    }
+}
+
+impl IntoUrls for Vec<String> {
+    fn into_urls(self) -> Vec<String> {
+        self
+    }
+}
+
+impl IntoUrls for &[&str] {
+    fn into_urls(self) -> Vec<String> {
+        self.iter().map(|url| url.to_string()).collect()
+    }
+}
+
+impl<const N: usize> IntoUrls for [&str; N] {
+    fn into_urls(self) -> Vec<String> {
+        self.as_slice().into_urls()
+    }
+}
+
 impl Downloader {
     /// Creates a new downloader
     pub fn new(client: Client) -> Self {
         Self {
             tasks: Vec::new(),
+            next_id: 1,
             client,
             parallel_requests: Arc::new(Semaphore::new(MAX_PARALLELS_REQUESTS)),
+            settings: TaskSettings::default(),
         }
     }
 
@@ -59,7 +351,9 @@ impl Downloader {
     }
 
     /// Add a download task
-    pub fn add_task(&mut self, task: DownloadTask) {
+    pub fn add_task(&mut self, mut task: DownloadTask) {
+        task.id = self.next_id;
+        self.next_id += 1;
         self.tasks.push(task);
     }
 
@@ -71,224 +365,821 @@ impl Downloader {
         self.tasks.is_empty()
     }
 
-    /// Downloads files with resume support
+    /// The limiter of the aggregate download speed, its rate can be changed during the download
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.settings.rate_limiter
+    }
+
+    /// Cancelling the token stops the download, see [`DownloaderBuilder::with_cancellation_token`]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.settings.cancellation
+    }
+
+    /// Downloads files with resume support.
+    ///
+    /// Partial `.part` files of the previous run are continued with HTTP Range requests.
     pub async fn resume_download(&self) -> DownloadResult {
-        todo!()
+        self.download_internal(self.tasks.iter().cloned(), true).await
     }
 
     /// Downloads files asynchronously
     pub async fn download_all(&self) -> DownloadResult {
-        self.download_internal(self.tasks.iter().map(|t| t.clone())).await
+        self.download_internal(self.tasks.iter().cloned(), false).await
     }
 
     /// Consumes all tasks and downloads them asynchronously
     pub async fn download_all_consume(&mut self) -> DownloadResult {
         let tasks = std::mem::take(&mut self.tasks);
-        self.download_internal(tasks.into_iter()).await
+        self.download_internal(tasks.into_iter(), false).await
     }
 
-    async fn download_internal<I>(&self, tasks: I) -> DownloadResult
+    async fn download_internal<I>(&self, tasks: I, resume: bool) -> DownloadResult
     where
         I: IntoIterator<Item = DownloadTask> + ExactSizeIterator,
     {
         let mut handles = tokio::task::JoinSet::new();
-        let mut result = DownloadResult::new(self.task_count());
+        let mut result = DownloadResult::new(tasks.len());
+        // Outcomes of the running tasks in case they panic
+        let mut running = HashMap::new();
+
+        let cancellation = &self.settings.cancellation;
 
         for task in tasks {
             let client = self.client.clone();
-            let permit = self.parallel_requests.clone().acquire_owned().await.unwrap();
-
-            handles.spawn(async move {
+            let settings = self.settings.clone();
+            let outcome = TaskOutcome::new(&task);
+
+            // The tasks waiting for a permit are never started after the cancellation
+            let permit = tokio::select! {
+                biased;
+                _ = cancellation.cancelled() => None,
+                permit = self.parallel_requests.clone().acquire_owned() => Some(permit.unwrap()),
+            };
+            let Some(permit) = permit else {
+                result.outcomes.push(outcome.fail(DownloadError::Cancelled));
+                continue;
+            };
+
+            let handle = handles.spawn(async move {
                 let _permit = permit; // Holding the permit until the task is completed
-                Self::download_file(&client, task).await
+                Self::download_file(&client, task, resume, &settings).await
             });
+            running.insert(handle.id(), outcome);
         }
 
-        while let Some(res) = handles.join_next().await {
+        while let Some(res) = handles.join_next_with_id().await {
             match res {
-                Ok(Ok(_)) => {}
-                Ok(Err(e)) => result.errors.push(e),
-                Err(join_err) => result.errors.push(anyhow::anyhow!("Task failed: {}", join_err)),
+                Ok((task_id, outcome)) => {
+                    running.remove(&task_id);
+                    result.outcomes.push(outcome);
+                }
+                Err(join_err) => {
+                    if let Some(outcome) = running.remove(&join_err.id()) {
+                        let error = DownloadError::Other(anyhow::anyhow!("Task failed: {}", join_err));
+                        result.outcomes.push(outcome.fail(error));
+                    }
+                }
             }
         }
 
+        result.outcomes.sort_by_key(|outcome| outcome.id);
         result
     }
 
-    fn validate_urls(urls: Vec<String>) -> (Vec<String>, Vec<anyhow::Error>) {
-        let mut valid_urls = vec![];
-        let mut errors = vec![];
+    pub fn is_valid_url(url: &str) -> bool {
+        reqwest::Url::parse(url).is_ok()
+    }
 
-        for url_string in urls {
-            if Self::is_valid_url(&url_string) {
-                valid_urls.push(url_string);
-            } else {
-                errors.push(anyhow::anyhow!("Can't parse url: {}", url_string));
+    /// Downloads the file of the task, describing what happened in the outcome
+    async fn download_file(
+        client: &Client,
+        mut task: DownloadTask,
+        resume: bool,
+        settings: &TaskSettings,
+    ) -> TaskOutcome {
+        let started = Instant::now();
+        task.reporter.lock().await.on_task_start(task.id);
+        let mut outcome = TaskOutcome::new(&task);
+        let result = Self::download_task(client, &mut task, resume, settings, &mut outcome).await;
+
+        outcome.output = task.output;
+        outcome.elapsed = started.elapsed();
+        match result {
+            Ok(status) => {
+                outcome.status = status;
+                outcome
             }
+            Err(err) => outcome.fail(err),
         }
-
-        (valid_urls, errors)
     }
 
-    pub fn is_valid_url(url: &str) -> bool {
-        reqwest::Url::parse(url).is_ok()
+    /// Prepares the output and repeats the attempts, falling back to the mirrors
+    async fn download_task(
+        client: &Client,
+        task: &mut DownloadTask,
+        resume: bool,
+        settings: &TaskSettings,
+        outcome: &mut TaskOutcome,
+    ) -> Result<TaskStatus, DownloadError> {
+        // Preparation, the output of a task named by the response is checked once the name is known.
+        // Input entries may place files into subdirectories, which are created for the download only
+        if let Some(dir) = task.output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+
+        // The policy applies to the output also when a partial file is resumed, as it's moved into place in the end
+        if !task.name_from_response && !Self::handle_existing_file(task).await? {
+            return Ok(TaskStatus::Skipped);
+        }
+
+        let retry_policy = &settings.retry_policy;
+        let mut attempt = 0;
+        let mut resume = resume;
+        let mut mirrors = std::mem::take(&mut task.mirrors).into_iter();
+
+        let status = loop {
+            outcome.attempts += 1;
+            match Self::download_attempt(client, task, resume, settings, outcome).await {
+                Ok(status) => break status,
+                Err(err) if attempt < retry_policy.retries && retry::is_retryable(&err) => {
+                    attempt += 1;
+                    let delay = retry_policy.delay(attempt, retry::retry_after(&err));
+                    task.reporter
+                        .lock()
+                        .await
+                        .on_retry(attempt, retry_policy.retries, delay, &err);
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = settings.cancellation.cancelled() => {}
+                    }
+
+                    // Only the file written by a failed attempt of this run is known to be ours,
+                    // a `.part` of an earlier run is continued only with --resume
+                    resume = resume || task.part_written;
+                }
+                Err(err) => {
+                    if retry::is_mirror_failure(&err)
+                        && let Some(mirror) = mirrors.next()
+                    {
+                        task.reporter
+                            .lock()
+                            .await
+                            .on_mirror(&auth::redact_url(&mirror), &err);
+                        task.url = mirror;
+                        task.part_written = false;
+                        attempt = 0;
+                        // The validators of the received part belong to the previous URL, so it's
+                        // continued only if the checksum verifies the whole file in the end
+                        resume = task.checksum.is_some();
+                        continue;
+                    }
+
+                    outcome.bytes = Self::partial_size(&task.part_path())
+                        .await
+                        .ok()
+                        .flatten()
+                        .unwrap_or(0);
+                    Self::release_reserved_name(task, settings).await;
+                    let err = DownloadError::from(err);
+                    task.reporter.lock().await.on_error(&err);
+                    return Err(err);
+                }
+            }
+        };
+
+        if status == TaskStatus::Succeeded {
+            task.reporter
+                .lock()
+                .await
+                .on_complete(&task.redacted_url(), &task.output);
+        }
+        Ok(status)
     }
 
-    async fn download_file(client: &Client, mut task: DownloadTask) -> Result<()> {
-        // Preparation
-        if Self::handle_existing_file(&mut task).await? {
-            return Err(anyhow::anyhow!("File exists: {}", task.output.display())
-                .context("Use -f --force to replace existing files"));
+    /// Makes a single attempt to download the file, continuing the partial file if `resume` is set.
+    ///
+    /// A task named by the response is skipped here if its output exists and the policy keeps it.
+    async fn download_attempt(
+        client: &Client,
+        task: &mut DownloadTask,
+        resume: bool,
+        settings: &TaskSettings,
+        outcome: &mut TaskOutcome,
+    ) -> Result<TaskStatus> {
+        if settings.cancellation.is_cancelled() {
+            return Err(DownloadError::Cancelled.into());
         }
 
+        let offset = if resume && !task.name_from_response {
+            Self::partial_size(&task.part_path()).await?.unwrap_or(0)
+        } else {
+            0
+        };
+
+        // Sending a request, continuing from the end of the partial file
+        let state = match offset {
+            0 => None,
+            _ => ResumeState::load(&task.output)
+                .await
+                .filter(|state| state.url == task.url),
+        };
+        // Without a stored validator the partial file can't be tied to the remote one,
+        // so it's continued only if the checksum verifies the whole file in the end
+        let offset = match state.as_ref().and_then(ResumeState::if_range) {
+            None if task.checksum.is_none() => 0,
+            _ => offset,
+        };
+
+        let mut headers = auth::merge_headers(&settings.headers, &task.headers);
+        if !headers.contains_key(header::AUTHORIZATION)
+            && let Some(authorization) = settings
+                .netrc
+                .as_ref()
+                .and_then(|netrc| netrc.authorization(&task.url))
         {
-            let mut reporter = task.reporter.lock().await;
-            reporter.on_request(&task.url);
+            headers.insert(header::AUTHORIZATION, authorization);
         }
+        let mut response =
+            Self::send_request(client, task, settings, &headers, offset, state.as_ref()).await?;
+
+        // The partial file doesn't match the remote one, start over. A server ignoring `If-Range`
+        // may send a part of another version of the file, so the validators of the part are checked too.
+        let changed = response.status() == StatusCode::PARTIAL_CONTENT
+            && state.as_ref().is_some_and(|state| !state.matches(&response));
+        if offset > 0 && (response.status() == StatusCode::RANGE_NOT_SATISFIABLE || changed) {
+            response = Self::send_request(client, task, settings, &headers, 0, None).await?;
+        }
+        outcome.http_status = Some(response.status());
+        outcome.final_url = Some(auth::redact_url(response.url().as_str()).into_owned());
 
-        // Sending a request
-        let response = match client
-            .get(&task.url)
-            .send()
-            .await
-            .with_context(|| format!("Failed to GET: '{}'", &task.url))
-        {
-            Ok(response) => {
-                task.reporter.lock().await.on_response(&response);
-                response
+        // Checking the response status
+        if !response.status().is_success() {
+            return Err(StatusError::new(&task.redacted_url(), response.status(), response.headers()).into());
+        }
+
+        if task.name_from_response {
+            // Claiming the name and reserving the file under one lock, so concurrent tasks
+            // never download to the same path
+            let mut outputs = settings.outputs.lock().await;
+            Self::name_from_response(task, &response);
+            outputs.claim(&mut task.output, task.id, &task.url, settings.collision_policy)?;
+
+            if !Self::handle_existing_file(task).await? {
+                return Ok(TaskStatus::Skipped);
+            }
+            if resume && Self::partial_size(&task.part_path()).await?.is_some() {
+                // Continuing the partial file now that its name is known
+                drop(outputs);
+                return Box::pin(Self::download_attempt(client, task, resume, settings, outcome)).await;
+            }
+        }
+
+        // The server may ignore the Range header and send the whole file with 200 OK
+        let (offset, range_end) = if offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT {
+            let (end, total) = Self::check_content_range(&response, offset)?;
+            if let Some(total) = total
+                && end + 1 != total
+            {
+                anyhow::bail!(
+                    "Partial response ends at byte {} of {}: {}",
+                    end,
+                    total,
+                    auth::redact_url(response.url().as_str())
+                );
+            }
+            (offset, Some(end + 1))
+        } else {
+            (0, None)
+        };
+
+        // Get file size from Content-Range or Content-Length header (if any)
+        let total_size = range_end.or_else(|| Self::content_length(&response).map(|len| len + offset));
+
+        task.reporter.lock().await.on_file_size_known(total_size);
+
+        // Remember the validators to be able to resume an interrupted download
+        if let Some(state) = ResumeState::from_response(&task.url, &response) {
+            state.save(&task.output).await?;
+        }
+
+        // Download, splitting a fresh download into segments if the server supports it
+        // and the segments can be validated to come from the same file
+        let segments = total_size
+            .filter(|_| offset == 0 && segmented::accepts_ranges(&response))
+            .filter(|_| segmented::validator(&response).is_some())
+            .and_then(|total| segmented::plan_segments(total, settings.segments, settings.min_segment_size));
+
+        // From here on the `.part` file holds the bytes of this run
+        task.part_written = true;
+        let digest = match segments {
+            Some(segments) => {
+                match Self::download_segments(client, task, settings, &headers, response, segments).await {
+                    Err(err) if segmented::is_validator_mismatch(&err) => {
+                        // The file changed after the first response, its new version is downloaded in one stream
+                        Self::restart_single_stream(client, task, settings, &headers).await?
+                    }
+                    result => {
+                        result?;
+                        None
+                    }
+                }
             }
-            Err(e) => {
-                task.reporter.lock().await.on_error(&e);
-                return Err(e);
+            None => {
+                let expected = total_size.map(|total| total - offset);
+                Self::download_stream(task, settings, response, offset, expected).await?
             }
         };
 
-        // Checking the response status
+        Self::verify_size(task).await?;
+        Self::verify_checksum(task, digest).await?;
+        outcome.checksum = task.checksum.clone();
+        outcome.bytes = Self::partial_size(&task.part_path()).await?.unwrap_or(0);
+        Self::commit(task).await?;
+        Ok(TaskStatus::Succeeded)
+    }
+
+    /// Downloads the whole file again without segments, returning the digest like [`Self::download_stream`]
+    async fn restart_single_stream(
+        client: &Client,
+        task: &DownloadTask,
+        settings: &TaskSettings,
+        headers: &HeaderMap,
+    ) -> Result<Option<Vec<u8>>> {
+        let response = Self::send_request(client, task, settings, headers, 0, None).await?;
         if !response.status().is_success() {
-            let err = anyhow::anyhow!("Request {} failed with status: {}", &task.url, response.status());
-            task.reporter.lock().await.on_error(&err);
-            return Err(err);
+            return Err(StatusError::new(&task.redacted_url(), response.status(), response.headers()).into());
         }
 
-        // Get file size from Content-Length header (if any)
-        let total_size = response
+        let total_size = Self::content_length(&response);
+        task.reporter.lock().await.on_file_size_known(total_size);
+        if let Some(state) = ResumeState::from_response(&task.url, &response) {
+            state.save(&task.output).await?;
+        }
+        Self::download_stream(task, settings, response, 0, total_size).await
+    }
+
+    /// Sends a GET request, asking for the bytes after `offset` if it isn't zero
+    async fn send_request(
+        client: &Client,
+        task: &DownloadTask,
+        settings: &TaskSettings,
+        headers: &HeaderMap,
+        offset: u64,
+        state: Option<&ResumeState>,
+    ) -> Result<Response> {
+        task.reporter.lock().await.on_request(&task.redacted_url());
+
+        let mut request = client.get(&task.url).headers(headers.clone());
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+
+            if let Some(validator) = state.and_then(ResumeState::if_range) {
+                request = request.header(header::IF_RANGE, validator);
+            }
+        }
+
+        let response = settings
+            .until_cancelled(request.send())
+            .await?
+            .map_err(reqwest::Error::without_url)
+            .with_context(|| format!("Failed to GET: '{}'", task.redacted_url()))?;
+
+        task.reporter.lock().await.on_response(&response);
+        Ok(response)
+    }
+
+    /// Replaces the file name of the output with the one chosen from the response, keeping the directory
+    fn name_from_response(task: &mut DownloadTask, response: &Response) {
+        let fallback = match task.output.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => DownloadTask::sanitize_filename(&task.url),
+        };
+        task.output
+            .set_file_name(filename::from_response(response, &fallback));
+        task.name_from_response = false;
+    }
+
+    /// The length of the response body from the `Content-Length` header
+    fn content_length(response: &Response) -> Option<u64> {
+        response
             .headers()
-            .get(reqwest::header::CONTENT_LENGTH)
+            .get(header::CONTENT_LENGTH)
             .and_then(|ct_len| ct_len.to_str().ok())
-            .and_then(|ct_len| ct_len.parse::<u64>().ok());
+            .and_then(|ct_len| ct_len.parse::<u64>().ok())
+    }
 
-        task.reporter.lock().await.on_file_size_known(total_size);
+    /// Checks that the partial response starts exactly at `offset` and its `Content-Length` matches the range.
+    ///
+    /// Returns the last byte of the range and the total size of the file, if the server knows it.
+    fn check_content_range(response: &Response, offset: u64) -> Result<(u64, Option<u64>)> {
+        let content_range = response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Partial response without Content-Range: {}",
+                    auth::redact_url(response.url().as_str())
+                )
+            })?;
+
+        match resume::parse_content_range(content_range) {
+            Some((start, end, total))
+                if start == offset && end >= start && total.is_none_or(|total| end < total) =>
+            {
+                match Self::content_length(response) {
+                    Some(length) if length != end - start + 1 => Err(anyhow::anyhow!(
+                        "Content-Length {} doesn't match Content-Range '{}': {}",
+                        length,
+                        content_range,
+                        auth::redact_url(response.url().as_str())
+                    )),
+                    _ => Ok((end, total)),
+                }
+            }
+            _ => Err(anyhow::anyhow!(
+                "Unexpected Content-Range '{}' for offset {}: {}",
+                content_range,
+                offset,
+                auth::redact_url(response.url().as_str())
+            )),
+        }
+    }
 
-        // Download
-        Self::download_stream(&task, response).await?;
-        task.reporter.lock().await.on_complete(&task.url, &task.output);
-        Ok(())
+    /// Returns the size of an existing partial file, `None` if there is no file
+    async fn partial_size(output: &Path) -> Result<Option<u64>> {
+        match tokio::fs::metadata(output).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read partial file: {}", output.display())),
+        }
     }
 
-    /// Creates a new file and downloads the stream by calling callbacks
-    async fn download_stream(task: &DownloadTask, response: Response) -> Result<()> {
-        let file = tokio::fs::File::create(&task.output)
-            .await
-            .with_context(|| format!("Failed to create file: {}", &task.output.display()))?;
+    /// Creates a new file (or appends to the existing one if `offset` isn't zero)
+    /// and downloads the stream by calling callbacks.
+    ///
+    /// Fails with [`IncompleteBody`] if the body isn't `expected` bytes long.
+    /// Returns the digest of the whole file if the task has a checksum.
+    async fn download_stream(
+        task: &DownloadTask,
+        settings: &TaskSettings,
+        response: Response,
+        offset: u64,
+        expected: Option<u64>,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut hasher = task
+            .checksum
+            .as_ref()
+            .map(|checksum| Hasher::new(checksum.algorithm));
+        let part = task.part_path();
+        if let Some(hasher) = hasher.as_mut().filter(|_| offset > 0) {
+            hasher.update_from_file(&part).await?;
+        }
+
+        let file = if offset > 0 {
+            tokio::fs::OpenOptions::new().append(true).open(&part).await
+        } else {
+            tokio::fs::File::create(&part).await
+        }
+        .with_context(|| format!("Failed to create file: {}", part.display()))?;
         let mut writer = tokio::io::BufWriter::new(file);
         task.reporter.lock().await.on_file_create(&task.output);
 
         // Get the data stream from the response
-        task.reporter
-            .lock()
-            .await
-            .on_start_download(response.url().as_str(), &task.output);
+        {
+            let mut reporter = task.reporter.lock().await;
+            reporter.on_start_download(&auth::redact_url(response.url().as_str()), &task.output);
+
+            if offset > 0 {
+                reporter.on_resume(offset);
+            }
+        }
+        let url = auth::redact_url(response.url().as_str()).into_owned();
         let mut stream = response.bytes_stream();
+        let mut received = 0;
 
         // Read the stream and write it to a file
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.with_context(|| "Failed to read response chunk")?;
-            writer.write_all(&chunk).await?;
-            task.reporter.lock().await.on_progress(chunk.len() as u64);
+        let result: Result<()> = async {
+            while let Some(chunk) = settings.until_cancelled(stream.next()).await? {
+                let chunk = chunk
+                    .map_err(reqwest::Error::without_url)
+                    .with_context(|| "Failed to read response chunk")?;
+                writer.write_all(&chunk).await?;
+                received += chunk.len() as u64;
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update(&chunk);
+                }
+                task.reporter.lock().await.on_progress(chunk.len() as u64);
+                Self::throttle(task, settings, chunk.len()).await?;
+            }
+            Ok(())
         }
+        .await;
 
+        // Flushing even on failure, the written bytes are kept for resuming
         writer.flush().await?;
-        Ok(())
+        result?;
+
+        // The connection may be closed before the end of the body, the received part is kept for resuming
+        if let Some(expected) = expected
+            && received != expected
+        {
+            return Err(IncompleteBody {
+                url,
+                expected,
+                received,
+            }
+            .into());
+        }
+        Ok(hasher.map(Hasher::finalize))
     }
 
-    /// Checks the existence of a file and whether it can be written to.
-    ///
-    /// Returns `false` if the file exists and can be overwritten, and `true` otherwise.
-    async fn handle_existing_file(task: &mut DownloadTask) -> Result<bool> {
-        Ok(
-            if tokio::fs::try_exists(&task.output)
-                .await
-                .with_context(|| format!("Failed to check file existence: {}", task.output.display()))?
-            {
-                let mut reporter = task.reporter.lock().await;
-                reporter.on_file_exists(&task.output, task.overwrite);
-
-                if task.overwrite {
-                    tokio::fs::remove_file(&task.output).await.with_context(|| {
-                        format!("Failed to remove existing file: {}", task.output.display())
-                    })?;
-                    false
-                } else {
-                    true
-                }
-            } else {
-                false
-            },
-        )
+    /// Waits until the speed limits of the downloader and the task allow the received bytes
+    async fn throttle(task: &DownloadTask, settings: &TaskSettings, bytes: usize) -> Result<()> {
+        settings
+            .until_cancelled(settings.rate_limiter.acquire(bytes as u64))
+            .await?;
+        if let Some(limiter) = &task.rate_limiter {
+            settings.until_cancelled(limiter.acquire(bytes as u64)).await?;
+        }
+        Ok(())
     }
-}
 
-impl DownloadTask {
-    /// Try to get the filename from the URL
-    pub fn sanitize_filename(url: &str) -> String {
-        const MAX_FILENAME_LENGTH: usize = 100;
+    /// Compares the size of the downloaded file with the expected one, removing the file on mismatch
+    async fn verify_size(task: &DownloadTask) -> Result<()> {
+        let Some(expected) = task.size else {
+            return Ok(());
+        };
 
-        // Remove query and anchor parameters
-        let re_params = Regex::new(r"[?#].*$").unwrap();
-        let clean_url = re_params.replace(url, "");
+        let part = task.part_path();
+        let size = tokio::fs::metadata(&part)
+            .await
+            .with_context(|| format!("Failed to read file metadata: {}", part.display()))?
+            .len();
+
+        if size != expected {
+            tokio::fs::remove_file(&part).await.ok();
+            ResumeState::remove(&task.output).await.ok();
+            return Err(SizeMismatch {
+                path: task.output.clone(),
+                expected,
+                actual: size,
+            }
+            .into());
+        }
 
-        // Extract the last component of the path
-        let mut base = clean_url.split('/').last().unwrap_or("temp");
-        let re_special: Regex;
+        Ok(())
+    }
 
-        if base.is_empty() {
-            // Handling URLs ending in /
-            base = url.split("://").nth(1).unwrap_or("temp");
-            re_special = Regex::new(r"[^a-zA-Z0-9_]+").unwrap();
-        } else {
-            re_special = Regex::new(r"[^a-zA-Z0-9\_.]+").unwrap();
+    /// Compares the digest of the downloaded file with the expected one, removing the file on mismatch.
+    ///
+    /// `digest` is the already computed digest, otherwise the file is hashed.
+    async fn verify_checksum(task: &DownloadTask, digest: Option<Vec<u8>>) -> Result<()> {
+        let Some(expected) = &task.checksum else {
+            return Ok(());
+        };
+
+        let digest = match digest {
+            Some(digest) => digest,
+            None => checksum::hash_file(&task.part_path(), expected.algorithm).await?,
+        };
+
+        if digest != expected.digest {
+            tokio::fs::remove_file(task.part_path()).await.ok();
+            ResumeState::remove(&task.output).await.ok();
+
+            return Err(ChecksumMismatch {
+                path: task.output.clone(),
+                expected: expected.clone(),
+                actual: Checksum {
+                    algorithm: expected.algorithm,
+                    digest,
+                },
+            }
+            .into());
         }
 
-        re_special
-            .replace_all(base, "_")
-            .trim_matches('_')
-            .chars()
-            .take(MAX_FILENAME_LENGTH)
-            .collect::<String>()
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::{sync::Arc, time::Duration};
+    /// Moves the verified partial file into place, so the output never holds an incomplete download
+    async fn commit(task: &DownloadTask) -> Result<()> {
+        let part = task.part_path();
 
-    use bytes::Bytes;
-    use rand::{Rng, SeedableRng, rngs::StdRng};
-    use warp::Filter;
+        // The content must be on the disk before the rename makes it visible
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&part)
+            .await
+            .with_context(|| format!("Failed to open file: {}", part.display()))?
+            .sync_all()
+            .await
+            .with_context(|| format!("Failed to sync file: {}", part.display()))?;
 
-    use crate::{
-        config::{Config, app::AppConfig},
-        reporter::{ReporterFactory, console_reporter::ConsoleReporterFactory},
-    };
+        if task.on_conflict == ConflictPolicy::Backup
+            && tokio::fs::try_exists(&task.output).await.unwrap_or(false)
+        {
+            conflict::backup(&task.output).await?;
+        }
 
-    use super::*;
+        tokio::fs::rename(&part, &task.output)
+            .await
+            .with_context(|| format!("Failed to move {} to {}", part.display(), task.output.display()))?;
+        ResumeState::remove(&task.output).await
+    }
 
-    #[test]
-    fn test_get_filename() {
-        assert_eq!(
-            DownloadTask::sanitize_filename("https://example.com/file.txt"),
-            "file.txt"
-        );
+    /// Removes the empty file that only reserved the name of a failed download,
+    /// so that resuming picks the same name again
+    async fn release_reserved_name(task: &mut DownloadTask, settings: &TaskSettings) {
+        let Some(reserved) = task.reserved_output.take() else {
+            return;
+        };
+        // Under the lock of the names chosen from the responses, which may be reserving the same file
+        let _outputs = settings.outputs.lock().await;
+        if tokio::fs::metadata(&reserved)
+            .await
+            .is_ok_and(|metadata| metadata.len() == 0)
+        {
+            tokio::fs::remove_file(&reserved).await.ok();
+        }
+    }
+
+    /// Applies the conflict policy of the task if the output file exists.
+    ///
+    /// Returns `false` if the download must be skipped.
+    async fn handle_existing_file(task: &mut DownloadTask) -> Result<bool> {
+        if task.on_conflict == ConflictPolicy::Rename {
+            // The name is reserved even if it's free, so concurrent tasks never share it
+            let output = conflict::reserve_free_path(&task.output).await?;
+            task.reserved_output = Some(output.clone());
+            if output != task.output {
+                task.reporter
+                    .lock()
+                    .await
+                    .on_file_exists(&task.output, task.on_conflict);
+                task.output = output;
+            }
+            return Ok(true);
+        }
+
+        if !tokio::fs::try_exists(&task.output)
+            .await
+            .with_context(|| format!("Failed to check file existence: {}", task.output.display()))?
+        {
+            return Ok(true);
+        }
+
+        task.reporter
+            .lock()
+            .await
+            .on_file_exists(&task.output, task.on_conflict);
+
+        match task.on_conflict {
+            ConflictPolicy::Fail => Err(DownloadError::FileExists {
+                path: task.output.clone(),
+            }
+            .into()),
+            // The existing file is replaced or moved only when the new one is complete
+            ConflictPolicy::Overwrite | ConflictPolicy::Backup => Ok(true),
+            ConflictPolicy::Skip => Ok(false),
+            ConflictPolicy::Rename => unreachable!("Renaming is handled above"),
+        }
+    }
+}
+
+impl DownloadTask {
+    pub fn new(
+        url: &str,
+        output: impl AsRef<Path>,
+        on_conflict: ConflictPolicy,
+        reporter: Arc<Mutex<dyn DownloadReporter>>,
+    ) -> Self {
+        Self {
+            id: 0,
+            url: url.to_string(),
+            mirrors: Vec::new(),
+            output: output.as_ref().to_path_buf(),
+            on_conflict,
+            reporter,
+            checksum: None,
+            headers: HeaderMap::new(),
+            rate_limiter: None,
+            size: None,
+            name_from_response: false,
+            reserved_output: None,
+            part_written: false,
+        }
+    }
+
+    /// Sets the expected digest of the downloaded file
+    pub fn with_checksum(mut self, checksum: Option<Checksum>) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Sets the fallback URLs of the same file, tried in order after the main one fails
+    pub fn with_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Sets the request headers of the task
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the expected size of the downloaded file
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Chooses the file name from `Content-Disposition`, the redirected URL and `Content-Type`
+    /// of the response, using the name of `output` as the fallback
+    pub fn with_name_from_response(mut self, enable: bool) -> Self {
+        self.name_from_response = enable;
+        self
+    }
+
+    /// Limits the speed of the task in bytes per second.
+    ///
+    /// Clones of the task share the limiter, so its rate can be changed during the download.
+    pub fn with_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(bytes_per_sec));
+        self
+    }
+
+    /// Path of the file the download is written to until it's complete and verified
+    pub fn part_path(&self) -> PathBuf {
+        let mut path = self.output.as_os_str().to_owned();
+        path.push(PART_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// The URL without credentials, safe to show
+    pub fn redacted_url(&self) -> Cow<'_, str> {
+        auth::redact_url(&self.url)
+    }
+
+    /// Try to get the filename from the URL
+    pub fn sanitize_filename(url: &str) -> String {
+        const MAX_FILENAME_LENGTH: usize = 100;
+
+        // Remove query and anchor parameters
+        let re_params = Regex::new(r"[?#].*$").unwrap();
+        let clean_url = re_params.replace(url, "");
+
+        // Extract the last component of the path
+        let mut base = clean_url.split('/').next_back().unwrap_or("temp");
+        let re_special: Regex;
+
+        if base.is_empty() {
+            // Handling URLs ending in /
+            base = url.split("://").nth(1).unwrap_or("temp");
+            // Credentials must not end up in the filename
+            base = base.rsplit_once('@').map_or(base, |(_, host)| host);
+            re_special = Regex::new(r"[^a-zA-Z0-9_]+").unwrap();
+        } else {
+            re_special = Regex::new(r"[^a-zA-Z0-9\_.]+").unwrap();
+        }
+
+        re_special
+            .replace_all(base, "_")
+            .trim_matches('_')
+            .chars()
+            .take(MAX_FILENAME_LENGTH)
+            .collect::<String>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bytes::Bytes;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use warp::Filter;
+
+    use crate::{
+        config::{Config, app::AppConfig},
+        reporter::{
+            ReporterFactory, console_reporter::ConsoleReporterFactory, silent_reporter::SilentReporter,
+        },
+    };
+
+    use super::*;
+    use checksum::{ChecksumAlgorithm, manifest::ChecksumManifest};
+    use conflict::CollisionPolicy;
+
+    #[test]
+    fn test_get_filename() {
+        assert_eq!(
+            DownloadTask::sanitize_filename("https://example.com/file.txt"),
+            "file.txt"
+        );
         assert_eq!(
             DownloadTask::sanitize_filename("https://example.com/file.txt?param=value"),
             "file.txt"
@@ -401,7 +1292,7 @@ mod tests {
         tokio::spawn(server);
 
         let config = AppConfig::load().unwrap();
-        let mut builder = DownloaderBuilder::from(&config);
+        let mut builder = DownloaderBuilder::try_from(&config).unwrap();
         let reporter_factory = ConsoleReporterFactory::new(&config.progress_bar, &config.output);
 
         for file in filenames {
@@ -410,7 +1301,7 @@ mod tests {
             builder.add_task(
                 &url,
                 output,
-                false,
+                ConflictPolicy::Fail,
                 Arc::new(Mutex::new(reporter_factory.create())),
             );
         }
@@ -419,20 +1310,1576 @@ mod tests {
         ctrlc::try_set_handler({
             move || {
                 for file in filenames {
-                    std::fs::remove_file(&file).ok();
+                    std::fs::remove_file(file).ok();
                 }
                 std::process::exit(0);
             }
         })
         .ok();
 
-        let (downloader, errors) = builder.build().unwrap();
+        let (downloader, _) = builder.build().unwrap();
         let result = downloader.download_all().await;
 
         for file in filenames {
-            std::fs::remove_file(&file).ok();
+            std::fs::remove_file(file).ok();
+        }
+
+        assert_eq!(result.errors().len(), 0, "Download failed: {:#?}", result);
+    }
+
+    /// Serves `content` honoring `Range` requests, like most static file servers do.
+    /// Returns the server address and the number of received requests.
+    fn serve_ranges(
+        content: &'static [u8],
+        etag: &'static str,
+    ) -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+
+        let route = warp::path("file.bin")
+            .and(warp::header::optional::<String>("range"))
+            .and(warp::header::optional::<String>("if-range"))
+            .map(move |range: Option<String>, if_range: Option<String>| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+                let range = range
+                    .filter(|_| if_range.as_deref().is_none_or(|v| v == etag))
+                    .and_then(|range| {
+                        let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+                        let end = end.parse::<usize>().map_or(content.len(), |end| end + 1);
+                        Some((start.parse::<usize>().ok()?, end.min(content.len())))
+                    });
+
+                let mut reply = match range {
+                    Some((start, _)) if start >= content.len() => {
+                        let mut reply = warp::reply::Response::new(warp::hyper::Body::empty());
+                        *reply.status_mut() = warp::http::StatusCode::RANGE_NOT_SATISFIABLE;
+                        return reply;
+                    }
+                    Some((start, end)) => {
+                        let mut reply = warp::reply::Response::new(content[start..end].into());
+                        *reply.status_mut() = warp::http::StatusCode::PARTIAL_CONTENT;
+                        let range = format!("bytes {}-{}/{}", start, end - 1, content.len());
+                        reply
+                            .headers_mut()
+                            .insert("content-range", range.parse().unwrap());
+                        reply
+                    }
+                    None => warp::reply::Response::new(content.into()),
+                };
+                reply.headers_mut().insert("etag", etag.parse().unwrap());
+                reply
+                    .headers_mut()
+                    .insert("accept-ranges", "bytes".parse().unwrap());
+                reply
+            });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, requests)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dw-test-{}-{}", std::process::id(), name))
+    }
+
+    /// A task without progress output
+    fn silent_task(url: &str, output: impl AsRef<Path>, on_conflict: ConflictPolicy) -> DownloadTask {
+        DownloadTask::new(url, output, on_conflict, Arc::new(Mutex::new(SilentReporter)))
+    }
+
+    /// Downloads the tasks added by `add_tasks` into a new temporary directory, which is removed afterwards.
+    ///
+    /// Returns the result and the files left in the directory with their content, sorted by name.
+    async fn download_in_dir(
+        name: &str,
+        mut builder: DownloaderBuilder,
+        add_tasks: impl FnOnce(&mut DownloaderBuilder, &Path),
+    ) -> (DownloadResult, Vec<(String, Vec<u8>)>) {
+        let dir = temp_path(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        add_tasks(&mut builder, &dir);
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry.file_name().into_string().unwrap(),
+                    std::fs::read(entry.path()).unwrap(),
+                )
+            })
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&dir).ok();
+        (result, files)
+    }
+
+    async fn resume_helper(partial: &[u8], etag: Option<&str>) -> (DownloadResult, Vec<u8>) {
+        let content: &[u8] = &[7u8; 4096];
+        let (addr, _) = serve_ranges(content, "\"v1\"");
+        let output = temp_path(&format!("resume-{}-{}", partial.len(), etag.map_or(0, str::len)));
+        let url = format!("http://{}/file.bin", addr);
+
+        let task = silent_task(&url, &output, ConflictPolicy::Fail);
+        std::fs::write(task.part_path(), partial).unwrap();
+        if let Some(etag) = etag {
+            ResumeState {
+                url: url.clone(),
+                etag: Some(etag.to_string()),
+                last_modified: None,
+            }
+            .save(&output)
+            .await
+            .unwrap();
+        }
+
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(task.clone());
+        let (downloader, _) = builder.build().unwrap();
+        let result = downloader.resume_download().await;
+
+        let downloaded = std::fs::read(&output).unwrap();
+        let state_exists = ResumeState::path_for(&output).exists();
+        let part_exists = task.part_path().exists();
+        std::fs::remove_file(&output).ok();
+        std::fs::remove_file(ResumeState::path_for(&output)).ok();
+
+        assert!(!state_exists, "Resume state must be removed after completion");
+        assert!(!part_exists, "The partial file must be moved into place");
+        (result, downloaded)
+    }
+
+    #[tokio::test]
+    async fn test_resume_appends_to_partial_file() {
+        // The partial content differs from the remote one to make sure that only the rest is requested
+        let (result, downloaded) = resume_helper(&[1u8; 1000], Some("\"v1\"")).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, [[1u8; 1000].as_slice(), &[7u8; 3096]].concat());
+    }
+
+    #[tokio::test]
+    async fn test_resume_restarts_on_changed_file() {
+        // The If-Range validator doesn't match, so the server responds with the whole file
+        let (result, downloaded) = resume_helper(&[1u8; 1000], Some("\"v0\"")).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, vec![7u8; 4096]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_without_validator_restarts() {
+        // Nothing ties the partial file to the remote one
+        let (result, downloaded) = resume_helper(&[1u8; 1000], None).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, vec![7u8; 4096]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_without_validator_is_verified_by_checksum() {
+        let (addr, _) = serve_ranges(&[7u8; 4096], "\"v1\"");
+        let output = temp_path("resume-checksum");
+        let digest = {
+            let mut hasher = Hasher::new(ChecksumAlgorithm::Sha256);
+            hasher.update(&[7u8; 4096]);
+            hasher.finalize()
+        };
+
+        let task = silent_task(
+            &format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+        )
+        .with_checksum(Checksum::new(ChecksumAlgorithm::Sha256, &hex::encode(digest)).ok());
+        // Continued without a validator, the different content is caught by the checksum
+        std::fs::write(task.part_path(), [1u8; 1000]).unwrap();
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(task);
+        let result = builder.build().unwrap().0.resume_download().await;
+        ResumeState::remove(&output).await.ok();
+
+        assert_eq!(result.errors().len(), 1, "{:#?}", result);
+        assert!(matches!(result.errors()[0], DownloadError::ChecksumMismatch(_)));
+        assert!(!output.exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_restarts_on_part_of_changed_file() {
+        // The server ignores If-Range and sends a part of another version of the file
+        let addr = serve_raw(vec![
+            raw_response(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 1000-4095/4096\r\n\
+                 Content-Length: 3096\r\nETag: \"v2\"",
+                &[7u8; 3096],
+            ),
+            raw_response(
+                "HTTP/1.1 200 OK\r\nContent-Length: 4096\r\nETag: \"v2\"",
+                &[7u8; 4096],
+            ),
+        ]);
+        let output = temp_path("resume-ignored-if-range");
+        let url = format!("http://{}/file.bin", addr);
+
+        let task = silent_task(&url, &output, ConflictPolicy::Fail);
+        std::fs::write(task.part_path(), [1u8; 1000]).unwrap();
+        ResumeState {
+            url,
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        }
+        .save(&output)
+        .await
+        .unwrap();
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(task);
+        let result = builder.build().unwrap().0.resume_download().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+        ResumeState::remove(&output).await.ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, vec![7u8; 4096]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_restarts_on_unsatisfiable_range() {
+        let (result, downloaded) = resume_helper(&[1u8; 5000], Some("\"v1\"")).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, vec![7u8; 4096]);
+    }
+
+    /// Responds with `failure` status `failures` times before serving the file.
+    /// Returns the server address and the number of received requests.
+    fn serve_flaky(
+        failures: usize,
+        failure: warp::http::StatusCode,
+    ) -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+
+        let route = warp::path("flaky.bin").map(move || {
+            let mut reply = warp::reply::Response::new(vec![5u8; 512].into());
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                *reply.status_mut() = failure;
+            }
+            reply
+        });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, requests)
+    }
+
+    async fn retry_helper(failures: usize, failure: warp::http::StatusCode) -> (DownloadResult, usize) {
+        let (addr, requests) = serve_flaky(failures, failure);
+        let output = temp_path(&format!("retry-{}-{}", failures, failure.as_u16()));
+
+        let mut builder = DownloaderBuilder::new()
+            .with_retry_policy(RetryPolicy::new(3).with_base_delay(Duration::from_millis(10)));
+        builder.add_task(
+            format!("http://{}/flaky.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let (downloader, _) = builder.build().unwrap();
+        let result = downloader.download_all().await;
+        std::fs::remove_file(&output).ok();
+
+        (result, requests.load(std::sync::atomic::Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let (result, requests) = retry_helper(2, warp::http::StatusCode::SERVICE_UNAVAILABLE).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_retries() {
+        let (result, requests) = retry_helper(10, warp::http::StatusCode::TOO_MANY_REQUESTS).await;
+        assert_eq!(result.errors().len(), 1);
+        assert_eq!(requests, 4);
+    }
+
+    #[tokio::test]
+    async fn test_retry_restarts_partial_file_of_earlier_run() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Fails once before sending any bytes, then serves ranges of the file
+        let requests = Arc::new(AtomicUsize::new(0));
+        let route = warp::path("file.bin")
+            .and(warp::header::optional::<String>("range"))
+            .map(move |range: Option<String>| {
+                let mut reply = warp::reply::Response::new(warp::hyper::Body::empty());
+                if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    *reply.status_mut() = warp::http::StatusCode::SERVICE_UNAVAILABLE;
+                    return reply;
+                }
+                let start = range
+                    .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok())
+                    .unwrap_or(0usize);
+                *reply.body_mut() = vec![7u8; 4096 - start].into();
+                if start > 0 {
+                    *reply.status_mut() = warp::http::StatusCode::PARTIAL_CONTENT;
+                    let range = format!("bytes {}-4095/4096", start);
+                    reply
+                        .headers_mut()
+                        .insert("content-range", range.parse().unwrap());
+                }
+                reply.headers_mut().insert("etag", "\"v1\"".parse().unwrap());
+                reply
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let output = temp_path("retry-stale-part");
+        let url = format!("http://{}/file.bin", addr);
+
+        let task = silent_task(&url, &output, ConflictPolicy::Fail);
+        // Left by an earlier run, which isn't resumed
+        std::fs::write(task.part_path(), [1u8; 1000]).unwrap();
+        ResumeState {
+            url,
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        }
+        .save(&output)
+        .await
+        .unwrap();
+        let mut builder = DownloaderBuilder::new()
+            .with_retry_policy(RetryPolicy::new(3).with_base_delay(Duration::from_millis(10)));
+        builder.add_download_task(task);
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+        ResumeState::remove(&output).await.ok();
+
+        assert!(result.is_success(), "{:#?}", result);
+        assert_eq!(downloaded, vec![7u8; 4096]);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let (result, requests) = retry_helper(10, warp::http::StatusCode::NOT_FOUND).await;
+        assert_eq!(result.errors().len(), 1);
+        assert_eq!(requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_segmented_download() {
+        let content: &'static [u8] = (0..10_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>().leak();
+        let (addr, requests) = serve_ranges(content, "\"v1\"");
+        let output = temp_path("segmented");
+
+        let mut builder = DownloaderBuilder::new().with_segments(4, 1000);
+        builder.add_task(
+            format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let (downloader, _) = builder.build().unwrap();
+        let result = downloader.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, content);
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_segments_use_redirected_url() {
+        let content: &'static [u8] = (0..10_000u32).map(|i| (i % 241) as u8).collect::<Vec<_>>().leak();
+        let (addr, requests) = serve_ranges(content, "\"v1\"");
+
+        let redirects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&redirects);
+        let target: warp::http::Uri = format!("http://{}/file.bin", addr).parse().unwrap();
+        let route = warp::path("start").map(move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            warp::redirect::temporary(target.clone())
+        });
+        let (redirect_addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let output = temp_path("segmented-redirect");
+        let mut builder = DownloaderBuilder::new().with_segments(4, 1000);
+        builder.add_task(
+            format!("http://{}/start", redirect_addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, content);
+        assert_eq!(redirects.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_no_segments_without_validator() {
+        // The server accepts only one connection, so any segment request fails
+        let addr = serve_raw(vec![raw_response(
+            "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: 4000",
+            &[8u8; 4000],
+        )]);
+        let output = temp_path("segmented-no-validator");
+
+        let mut builder = DownloaderBuilder::new().with_segments(4, 1000).with_retries(0);
+        builder.add_task(
+            format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, [8u8; 4000]);
+    }
+
+    #[tokio::test]
+    async fn test_changed_file_restarts_without_segments() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // The file changes after the first response, so the segment requests get the whole new version
+        let requests = Arc::new(AtomicUsize::new(0));
+        let route = warp::path("file.bin")
+            .and(warp::header::optional::<String>("range"))
+            .map(move |range: Option<String>| {
+                let first = requests.fetch_add(1, Ordering::SeqCst) == 0 && range.is_none();
+                let (content, etag) = if first { (1u8, "\"v1\"") } else { (2u8, "\"v2\"") };
+                let mut reply = warp::reply::Response::new(vec![content; 4000].into());
+                reply.headers_mut().insert("etag", etag.parse().unwrap());
+                reply
+                    .headers_mut()
+                    .insert("accept-ranges", "bytes".parse().unwrap());
+                reply
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let output = temp_path("segmented-changed");
+
+        let mut builder = DownloaderBuilder::new().with_segments(4, 1000).with_retries(0);
+        builder.add_task(
+            format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, [2u8; 4000]);
+    }
+
+    async fn checksum_helper(segments: usize, checksum: Checksum) -> (DownloadResult, bool) {
+        let (addr, _) = serve_ranges(&[3u8; 4096], "\"v1\"");
+        let output = temp_path(&format!("checksum-{}-{}", segments, checksum.hex()));
+
+        let mut builder = DownloaderBuilder::new().with_segments(segments, 1024);
+        builder.add_download_task(
+            silent_task(
+                &format!("http://{}/file.bin", addr),
+                &output,
+                ConflictPolicy::Fail,
+            )
+            .with_checksum(Some(checksum)),
+        );
+        let (downloader, _) = builder.build().unwrap();
+        let result = downloader.download_all().await;
+
+        let exists = output.exists();
+        std::fs::remove_file(&output).ok();
+        (result, exists)
+    }
+
+    #[tokio::test]
+    async fn test_checksum_verified() {
+        for algorithm in [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Blake3] {
+            let digest = {
+                let mut hasher = Hasher::new(algorithm);
+                hasher.update(&[3u8; 4096]);
+                hasher.finalize()
+            };
+
+            for segments in [1, 4] {
+                let checksum = Checksum::new(algorithm, &hex::encode(&digest)).unwrap();
+                let (result, exists) = checksum_helper(segments, checksum).await;
+                assert!(result.errors().is_empty(), "{:#?}", result);
+                assert!(exists);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_removes_file() {
+        let checksum = Checksum::new(ChecksumAlgorithm::Md5, &"0".repeat(32)).unwrap();
+        let (result, exists) = checksum_helper(1, checksum).await;
+
+        assert_eq!(result.errors().len(), 1);
+        assert!(matches!(result.errors()[0], DownloadError::ChecksumMismatch(_)));
+        assert!(!exists, "The corrupted file must be removed");
+    }
+
+    #[tokio::test]
+    async fn test_mirror_without_checksum_restarts() {
+        static CONTENT: [u8; 100] = [2u8; 100];
+        let (addr, _) = serve_ranges(&CONTENT, "\"v2\"");
+        // The first mirror serves another version of the file and closes the connection halfway
+        let broken = serve_raw(vec![raw_response(
+            "HTTP/1.1 200 OK\r\nContent-Length: 100\r\nETag: \"v1\"",
+            &[1u8; 50],
+        )]);
+        let output = temp_path("mirror-restart");
+
+        let mut builder = DownloaderBuilder::new().with_retries(0);
+        builder.add_task(
+            vec![
+                format!("http://{}/file.bin", broken),
+                format!("http://{}/file.bin", addr),
+            ],
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_fallback() {
+        let route = warp::path!("good" / "file.bin")
+            .map(|| "content")
+            .or(warp::path!("corrupt" / "file.bin").map(|| "corrupted"));
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let output = temp_path("mirrors");
+        let digest = {
+            let mut hasher = Hasher::new(ChecksumAlgorithm::Sha256);
+            hasher.update(b"content");
+            hasher.finalize()
+        };
+        let urls: Vec<String> = ["missing", "corrupt", "good"]
+            .iter()
+            .map(|mirror| format!("http://{}/{}/file.bin", addr, mirror))
+            .collect();
+
+        let mut builder = DownloaderBuilder::new().with_retries(0);
+        builder.add_download_task(
+            silent_task(&urls[0], &output, ConflictPolicy::Fail)
+                .with_mirrors(urls[1..].to_vec())
+                .with_checksum(Some(
+                    Checksum::new(ChecksumAlgorithm::Sha256, &hex::encode(&digest)).unwrap(),
+                )),
+        );
+        builder.add_task(
+            [urls[0].as_str(), "not a url"],
+            temp_path("invalid-mirror"),
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let (downloader, rejected) = builder.build().unwrap();
+        let result = downloader.download_all().await;
+
+        let downloaded = std::fs::read_to_string(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert_eq!(rejected.len(), 1);
+        let error = rejected[0].error.as_ref().unwrap();
+        assert!(error.to_string().contains("not a url"), "{}", error);
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, "content");
+
+        let outcome = &result.outcomes[0];
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(outcome.final_url.as_deref(), Some(urls[2].as_str()));
+        assert_eq!(outcome.status, TaskStatus::Succeeded);
+        assert_eq!(outcome.http_status, Some(StatusCode::OK));
+        assert_eq!(outcome.bytes, 7);
+        assert!(outcome.checksum.is_some());
+        assert_eq!(outcome.output, output);
+    }
+
+    /// Serves the raw HTTP `responses` to the consecutive connections, closing each after the response
+    fn serve_raw(responses: Vec<Vec<u8>>) -> std::net::SocketAddr {
+        use tokio::io::AsyncReadExt;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                socket.write_all(&response).await.ok();
+            }
+        });
+        addr
+    }
+
+    fn raw_response(head: &str, body: &[u8]) -> Vec<u8> {
+        [format!("{}\r\nConnection: close\r\n\r\n", head).as_bytes(), body].concat()
+    }
+
+    #[tokio::test]
+    async fn test_truncated_body_is_retried() {
+        let addr = serve_raw(vec![
+            raw_response("HTTP/1.1 200 OK\r\nContent-Length: 100", &[6u8; 50]),
+            raw_response("HTTP/1.1 200 OK\r\nContent-Length: 100", &[6u8; 100]),
+        ]);
+        let output = temp_path("truncated");
+
+        let mut builder = DownloaderBuilder::new()
+            .with_retry_policy(RetryPolicy::new(1).with_base_delay(Duration::from_millis(10)));
+        builder.add_task(
+            format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+        let downloaded = std::fs::read(&output).unwrap_or_default();
+        std::fs::remove_file(&output).ok();
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(downloaded, [6u8; 100]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_response_must_reach_the_end() {
+        let addr = serve_raw(vec![raw_response(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 10-19/100\r\nContent-Length: 10\r\n\
+             ETag: \"v1\"",
+            &[6u8; 10],
+        )]);
+        let url = format!("http://{}/file.bin", addr);
+        let task = silent_task(&url, temp_path("short-range"), ConflictPolicy::Fail);
+        std::fs::write(task.part_path(), [6u8; 10]).unwrap();
+        ResumeState {
+            url,
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        }
+        .save(&task.output)
+        .await
+        .unwrap();
+
+        let mut builder = DownloaderBuilder::new().with_retries(0);
+        builder.add_download_task(task.clone());
+        let result = builder.build().unwrap().0.resume_download().await;
+        std::fs::remove_file(task.part_path()).ok();
+        ResumeState::remove(&task.output).await.ok();
+
+        assert_eq!(result.errors().len(), 1);
+        assert!(
+            result.errors()[0].to_string().contains("ends at byte 19 of 100"),
+            "{:#}",
+            result.errors()[0]
+        );
+        assert!(!task.output.exists());
+    }
+
+    #[tokio::test]
+    async fn test_tls_failure_is_classified() {
+        // A plain HTTP server answers the TLS handshake
+        let addr = serve_raw(vec![raw_response(
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 0",
+            b"",
+        )]);
+
+        let mut builder = DownloaderBuilder::new().with_retries(0);
+        builder.add_task(
+            format!("https://{}/file.bin", addr),
+            temp_path("tls"),
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        assert_eq!(result.errors().len(), 1);
+        assert!(
+            matches!(result.errors()[0], DownloadError::Tls(_)),
+            "{:?}",
+            result.errors()[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_keeps_partial_file() {
+        use tokio::io::AsyncReadExt;
+
+        // Sends the first half of the body and stalls
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let response = raw_response("HTTP/1.1 200 OK\r\nContent-Length: 20", &[7u8; 10]);
+            socket.write_all(&response).await.ok();
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let cancellation = CancellationToken::new();
+        let mut builder = DownloaderBuilder::new()
+            .with_parallel_requests(1)
+            .with_cancellation_token(cancellation.clone());
+        let tasks: Vec<_> = ["cancel-running", "cancel-waiting"]
+            .into_iter()
+            .map(|name| {
+                silent_task(
+                    &format!("http://{}/file.bin", addr),
+                    temp_path(name),
+                    ConflictPolicy::Fail,
+                )
+            })
+            .collect();
+        for task in &tasks {
+            builder.add_download_task(task.clone());
+        }
+        let (downloader, _) = builder.build().unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            cancellation.cancel();
+        });
+        let result = tokio::time::timeout(Duration::from_secs(10), downloader.download_all())
+            .await
+            .expect("The download must stop after the cancellation");
+
+        let partial = std::fs::read(tasks[0].part_path());
+        std::fs::remove_file(tasks[0].part_path()).ok();
+        ResumeState::remove(&tasks[0].output).await.ok();
+
+        assert_eq!(result.cancelled().count(), 2, "{:#?}", result);
+        assert!(result.errors().is_empty());
+        assert_eq!(result.outcomes[1].attempts, 0);
+        assert_eq!(partial.unwrap(), [7u8; 10]);
+        assert!(tasks.iter().all(|task| !task.output.exists()));
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_interrupts_throttling() {
+        static CONTENT: [u8; 40_000] = [5u8; 40_000];
+        let (addr, _) = serve_ranges(&CONTENT, "\"v1\"");
+        let output = temp_path("cancel-throttled");
+
+        // Without the cancellation the download would take about 40 seconds
+        let cancellation = CancellationToken::new();
+        let mut builder = DownloaderBuilder::new()
+            .with_rate_limit(1_000)
+            .with_cancellation_token(cancellation.clone());
+        let task = silent_task(
+            &format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Fail,
+        );
+        builder.add_download_task(task.clone());
+        let (downloader, _) = builder.build().unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            cancellation.cancel();
+        });
+        let result = tokio::time::timeout(Duration::from_secs(5), downloader.download_all())
+            .await
+            .expect("The throttled download must stop after the cancellation");
+
+        std::fs::remove_file(task.part_path()).ok();
+        ResumeState::remove(&output).await.ok();
+
+        assert_eq!(result.cancelled().count(), 1, "{:#?}", result);
+        assert!(!output.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_overwrite_keeps_existing_file() {
+        let (addr, _) = serve_ranges(&[4u8; 64], "\"v1\"");
+        let output = temp_path("failed-overwrite");
+        std::fs::write(&output, "previous").unwrap();
+
+        let task = silent_task(
+            &format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Overwrite,
+        )
+        .with_size(Some(1));
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(task.clone());
+        let result = builder.build().unwrap().0.download_all().await;
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        let part_exists = task.part_path().exists();
+        std::fs::remove_file(&output).ok();
+
+        assert_eq!(result.errors().len(), 1, "{:#?}", result);
+        assert_eq!(content, "previous");
+        assert!(!part_exists, "The rejected download must be removed");
+    }
+
+    #[tokio::test]
+    async fn test_outcomes_in_task_order() {
+        let route = warp::path!("slow").then(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "slow"
+        });
+        let route = route.or(warp::path!("fast").map(|| "fast"));
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let dir = temp_path("outcomes");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut builder = DownloaderBuilder::new().with_retries(0);
+        for name in ["slow", "missing", "fast"] {
+            builder.add_task(
+                format!("http://{}/{}", addr, name),
+                dir.join(name),
+                ConflictPolicy::Fail,
+                Arc::new(Mutex::new(SilentReporter)),
+            );
+        }
+        let result = builder.build().unwrap().0.download_all().await;
+        std::fs::remove_dir_all(&dir).ok();
+
+        let summary: Vec<_> = result
+            .outcomes
+            .iter()
+            .map(|outcome| (outcome.id, outcome.status, outcome.bytes))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, TaskStatus::Succeeded, 4),
+                (2, TaskStatus::Failed, 0),
+                (3, TaskStatus::Succeeded, 4)
+            ]
+        );
+        assert!(!result.is_success());
+        assert_eq!(result.succeeded().count(), 2);
+
+        let failed: Vec<_> = result.failed().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].http_status, Some(StatusCode::NOT_FOUND));
+        assert!(failed[0].url.ends_with("/missing"));
+        assert_eq!(result.errors().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_tasks_in_result() {
+        let (addr, _) = serve_ranges(b"data", "\"v1\"");
+        let output = temp_path("rejected");
+
+        let mut builder = DownloaderBuilder::new();
+        builder.add_invalid_entry(anyhow::anyhow!("Invalid entry on line 1"));
+        builder.add_task(
+            format!("http://{}/file.bin", addr),
+            &output,
+            ConflictPolicy::Overwrite,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        builder.add_task(
+            "not a url",
+            temp_path("rejected-invalid"),
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(SilentReporter)),
+        );
+        let (downloader, rejected) = builder.build().unwrap();
+        let mut result = downloader.download_all().await;
+        result.add_rejected(rejected);
+        std::fs::remove_file(&output).ok();
+
+        let summary: Vec<_> = result
+            .outcomes
+            .iter()
+            .map(|outcome| (outcome.id, outcome.status))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, TaskStatus::Failed),
+                (2, TaskStatus::Succeeded),
+                (3, TaskStatus::Failed)
+            ]
+        );
+        assert_eq!(result.total, 3);
+        assert!(!result.is_success());
+        assert!(matches!(
+            result.errors()[..],
+            [DownloadError::InvalidEntry(_), DownloadError::InvalidUrl { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_event_task_ids_match_outcomes() {
+        use crate::reporter::json_reporter::JsonReporterFactory;
+
+        let (addr, _) = serve_ranges(b"data", "\"v1\"");
+        let dir = temp_path("event_ids");
+        std::fs::create_dir_all(&dir).unwrap();
+        let events = dir.join("events.jsonl");
+        let factory = JsonReporterFactory::create_file(&events).unwrap();
+
+        // The reporters are created in the reverse order and the first task is rejected
+        let reporters: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(factory.create()))).collect();
+        let mut builder = DownloaderBuilder::new();
+        builder.add_task(
+            "not a url",
+            dir.join("invalid"),
+            ConflictPolicy::Fail,
+            reporters[2].clone(),
+        );
+        for (name, reporter) in ["a", "b"].into_iter().zip(reporters[..2].iter().rev()) {
+            builder.add_task(
+                format!("http://{}/file.bin?name={}", addr, name),
+                dir.join(name),
+                ConflictPolicy::Fail,
+                reporter.clone(),
+            );
+        }
+        let result = builder.build().unwrap().0.download_all().await;
+        drop(factory);
+
+        let content = std::fs::read_to_string(&events).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(result.is_success(), "{:#?}", result);
+        let ids: Vec<_> = result.outcomes.iter().map(|outcome| outcome.id).collect();
+        assert_eq!(ids, [2, 3]);
+        for line in content.lines() {
+            let event: serde_json::Value = serde_json::from_str(line).unwrap();
+            let expected = if event["url"].as_str().unwrap().ends_with("=a") {
+                2
+            } else {
+                3
+            };
+            assert_eq!(event["task"], expected, "{}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_event_headers() {
+        use crate::reporter::json_reporter::JsonReporterFactory;
+
+        let route = warp::path("file.txt").map(|| {
+            let mut reply = warp::reply::Response::new("data".into());
+            let headers = reply.headers_mut();
+            headers.insert("content-type", "text/plain".parse().unwrap());
+            headers.insert("etag", "\"v1\"".parse().unwrap());
+            headers.insert("set-cookie", "session=secret".parse().unwrap());
+            headers.insert("x-upload-token", "secret".parse().unwrap());
+            reply
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let dir = temp_path("response-headers");
+        std::fs::create_dir_all(&dir).unwrap();
+        let events = dir.join("events.jsonl");
+        let factory = JsonReporterFactory::create_file(&events).unwrap();
+
+        let mut builder = DownloaderBuilder::new();
+        builder.add_task(
+            format!("http://{}/file.txt", addr),
+            dir.join("file.txt"),
+            ConflictPolicy::Fail,
+            Arc::new(Mutex::new(factory.create())),
+        );
+        let result = builder.build().unwrap().0.download_all().await;
+        drop(factory);
+
+        let content = std::fs::read_to_string(&events).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(result.is_success(), "{:#?}", result);
+        assert!(!content.contains("secret"), "{}", content);
+        let response: serde_json::Value = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .find(|event: &serde_json::Value| event["event"] == "response")
+            .unwrap();
+        assert_eq!(
+            response["headers"],
+            serde_json::json!({ "content-length": "4", "content-type": "text/plain", "etag": "\"v1\"" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_rename_releases_only_its_reservation() {
+        let (addr, _) = serve_ranges(b"data", "\"v1\"");
+        let url = format!("http://{}/missing", addr);
+
+        let builder = DownloaderBuilder::new().with_retries(0);
+        let (result, files) = download_in_dir("release-reservation", builder, |builder, dir| {
+            // An empty file of the user at the fallback name of the task named by the response
+            std::fs::write(dir.join("user.txt"), "").unwrap();
+            builder.add_download_task(
+                silent_task(&url, dir.join("user.txt"), ConflictPolicy::Rename).with_name_from_response(true),
+            );
+            builder.add_download_task(silent_task(
+                &url,
+                dir.join("reserved.txt"),
+                ConflictPolicy::Rename,
+            ));
+        })
+        .await;
+
+        assert_eq!(result.failed().count(), 2, "{:#?}", result);
+        assert_eq!(files, [("user.txt".to_string(), Vec::new())]);
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policies() {
+        let (addr, _) = serve_ranges(&[4u8; 64], "\"v1\"");
+        let url = format!("http://{}/file.bin", addr);
+
+        for (policy, status, expected) in [
+            (
+                ConflictPolicy::Fail,
+                TaskStatus::Failed,
+                vec![("file.bin", vec![1u8])],
+            ),
+            (
+                ConflictPolicy::Skip,
+                TaskStatus::Skipped,
+                vec![("file.bin", vec![1u8])],
+            ),
+            (
+                ConflictPolicy::Overwrite,
+                TaskStatus::Succeeded,
+                vec![("file.bin", vec![4u8; 64])],
+            ),
+            (
+                ConflictPolicy::Rename,
+                TaskStatus::Succeeded,
+                vec![("file (1).bin", vec![4u8; 64]), ("file.bin", vec![1u8])],
+            ),
+            (
+                ConflictPolicy::Backup,
+                TaskStatus::Succeeded,
+                vec![("file.bin", vec![4u8; 64]), ("file.bin.~1~", vec![1u8])],
+            ),
+        ] {
+            let name = format!("conflict-{:?}", policy);
+            let (result, files) = download_in_dir(&name, DownloaderBuilder::new(), |builder, dir| {
+                std::fs::write(dir.join("file.bin"), [1u8]).unwrap();
+                builder.add_download_task(silent_task(&url, dir.join("file.bin"), policy));
+            })
+            .await;
+
+            assert_eq!(result.outcomes[0].status, status, "{:?}: {:#?}", policy, result);
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(name, content)| (name.to_string(), content))
+                .collect();
+            assert_eq!(files, expected, "{:?}", policy);
         }
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policies_apply_when_resuming() {
+        let (addr, _) = serve_ranges(&[4u8; 64], "\"v1\"");
+        let url = format!("http://{}/file.bin", addr);
+
+        for (policy, status, content, backup) in [
+            (ConflictPolicy::Fail, TaskStatus::Failed, vec![1u8], None),
+            (ConflictPolicy::Skip, TaskStatus::Skipped, vec![1u8], None),
+            (
+                ConflictPolicy::Backup,
+                TaskStatus::Succeeded,
+                vec![4u8; 64],
+                Some(vec![1u8]),
+            ),
+        ] {
+            let dir = temp_path(&format!("conflict-resume-{:?}", policy));
+            std::fs::create_dir_all(&dir).unwrap();
+            let output = dir.join("file.bin");
+            std::fs::write(&output, [1u8]).unwrap();
+
+            let task = silent_task(&url, &output, policy);
+            std::fs::write(task.part_path(), [4u8; 10]).unwrap();
+            ResumeState {
+                url: url.clone(),
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+            }
+            .save(&output)
+            .await
+            .unwrap();
+            let mut builder = DownloaderBuilder::new();
+            builder.add_download_task(task);
+            let result = builder.build().unwrap().0.resume_download().await;
+
+            let downloaded = std::fs::read(&output).unwrap();
+            let backed_up = std::fs::read(dir.join("file.bin.~1~")).ok();
+            std::fs::remove_dir_all(&dir).ok();
+
+            assert_eq!(result.outcomes[0].status, status, "{:?}: {:#?}", policy, result);
+            assert_eq!(downloaded, content, "{:?}", policy);
+            assert_eq!(backed_up, backup, "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_name_from_response() {
+        let route = warp::path!("download" / String).map(|name: String| {
+            let mut reply = warp::reply::Response::new("content".into());
+            let headers = reply.headers_mut();
+            headers.insert("content-type", "application/pdf".parse().unwrap());
+            match name.as_str() {
+                "disposition" => {
+                    let value = "attachment; filename=\"report.pdf\"; filename*=UTF-8''%C3%BCber.pdf";
+                    headers.insert("content-disposition", value.parse().unwrap());
+                }
+                "traversal" => {
+                    let value = "attachment; filename=\"../traversal.pdf\"";
+                    headers.insert("content-disposition", value.parse().unwrap());
+                }
+                _ => {}
+            }
+            reply
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let (result, files) =
+            download_in_dir("name-from-response", DownloaderBuilder::new(), |builder, dir| {
+                for name in ["disposition", "traversal", "plain"] {
+                    let url = format!("http://{}/download/{}", addr, name);
+                    builder.add_download_task(
+                        silent_task(&url, dir.join(name), ConflictPolicy::Fail).with_name_from_response(true),
+                    );
+                }
+            })
+            .await;
+
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        let names: Vec<_> = files.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["plain.pdf", "traversal.pdf", "über.pdf"]);
+        assert!(!std::env::temp_dir().join("traversal.pdf").exists());
+    }
+
+    #[tokio::test]
+    async fn test_response_name_collisions() {
+        let route = warp::path!(String).map(|name: String| {
+            warp::reply::with_header(
+                name.repeat(4),
+                "content-disposition",
+                "attachment; filename=\"report.pdf\"",
+            )
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
 
-        assert_eq!(result.errors.len(), 0, "Download failed: {:#?}", result);
+        for (policy, expected) in [
+            (CollisionPolicy::Reject, vec![("report.pdf", "cccc")]),
+            (
+                CollisionPolicy::Rename,
+                vec![
+                    ("report (1).pdf", "aaaa"),
+                    ("report (2).pdf", "bbbb"),
+                    ("report.pdf", "cccc"),
+                ],
+            ),
+        ] {
+            let builder = DownloaderBuilder::new().with_collision_policy(policy);
+            let name = format!("response-collisions-{:?}", policy);
+            let (result, files) = download_in_dir(&name, builder, |builder, dir| {
+                let url = format!("http://{}/c", addr);
+                builder.add_download_task(silent_task(&url, dir.join("report.pdf"), ConflictPolicy::Fail));
+                for name in ["a", "b"] {
+                    let url = format!("http://{}/{}", addr, name);
+                    builder.add_download_task(
+                        silent_task(&url, dir.join(name), ConflictPolicy::Fail).with_name_from_response(true),
+                    );
+                }
+            })
+            .await;
+
+            // The concurrent tasks may get the numbered names in any order
+            let (names, mut contents): (Vec<_>, Vec<_>) = files.into_iter().unzip();
+            contents.sort();
+            let (expected_names, expected_contents): (Vec<_>, Vec<_>) = expected
+                .into_iter()
+                .map(|(name, content)| (name, content.as_bytes().to_vec()))
+                .unzip();
+            assert_eq!(names, expected_names, "{:?}", policy);
+            assert_eq!(contents, expected_contents, "{:?}", policy);
+            match policy {
+                CollisionPolicy::Reject => {
+                    assert_eq!(result.succeeded().count(), 1);
+                    assert!(
+                        result
+                            .errors()
+                            .iter()
+                            .all(|err| matches!(err, DownloadError::OutputCollision { .. })),
+                        "{:#?}",
+                        result
+                    );
+                    assert_eq!(result.errors().len(), 2);
+                }
+                CollisionPolicy::Rename => assert!(result.is_success(), "{:#?}", result),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expected_size() {
+        let (addr, _) = serve_ranges(&[3u8; 4096], "\"v1\"");
+
+        for (size, valid) in [(4096, true), (4000, false)] {
+            let output = temp_path(&format!("size-{}", size));
+            let mut builder = DownloaderBuilder::new();
+            builder.add_download_task(
+                silent_task(
+                    &format!("http://{}/file.bin", addr),
+                    &output,
+                    ConflictPolicy::Fail,
+                )
+                .with_size(Some(size)),
+            );
+            let result = builder.build().unwrap().0.download_all().await;
+
+            assert_eq!(result.errors().is_empty(), valid, "{:#?}", result);
+            assert_eq!(output.exists(), valid);
+            std::fs::remove_file(&output).ok();
+        }
+    }
+
+    #[test]
+    fn test_checksum_manifest_applied_on_build() {
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let manifest = ChecksumManifest::parse(&format!("{}  app.tar.gz\n", sha256), "SHA256SUMS").unwrap();
+
+        let mut builder = DownloaderBuilder::new()
+            .with_checksum_manifest(manifest)
+            .with_required_checksum(true);
+        for url in [
+            "https://example.com/v1/app.tar.gz",
+            "https://example.com/v1/app.zip",
+        ] {
+            builder.add_task(
+                url,
+                DownloadTask::sanitize_filename(url),
+                ConflictPolicy::Fail,
+                Arc::new(Mutex::new(SilentReporter)),
+            );
+        }
+
+        let (downloader, rejected) = builder.build().unwrap();
+        assert_eq!(downloader.task_count(), 1);
+        assert_eq!(downloader.tasks[0].checksum.as_ref().unwrap().hex(), sha256);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].id, 2);
+        assert!(matches!(
+            rejected[0].error,
+            Some(DownloadError::MissingChecksum { .. })
+        ));
+        assert!(rejected[0].url.ends_with("app.zip"), "{:#?}", rejected);
+    }
+
+    #[test]
+    fn test_output_collisions() {
+        let build = |policy| {
+            let mut builder = DownloaderBuilder::new().with_collision_policy(policy);
+            for (url, output) in [
+                ("https://a.com/x/file.zip", "file.zip"),
+                ("https://b.com/y/file.zip", "./file.zip"),
+                ("https://a.com/x/file.zip", "file.zip"),
+                ("https://c.com/file.zip", "file.zip"),
+                ("https://a.com/x/file.zip", "copy.zip"),
+            ] {
+                builder.add_task(
+                    url,
+                    output,
+                    ConflictPolicy::Fail,
+                    Arc::new(Mutex::new(SilentReporter)),
+                );
+            }
+            let (downloader, rejected) = builder.build().unwrap();
+            let outputs: Vec<_> = downloader.tasks.iter().map(|task| task.output.clone()).collect();
+            (outputs, rejected)
+        };
+
+        let (outputs, rejected) = build(CollisionPolicy::Reject);
+        assert_eq!(outputs, [PathBuf::from("file.zip"), "copy.zip".into()]);
+        let ids: Vec<_> = rejected.iter().map(|outcome| outcome.id).collect();
+        assert_eq!(ids, [2, 3, 4], "{:#?}", rejected);
+        let error = rejected[0].error.as_ref().unwrap();
+        assert!(matches!(error, DownloadError::OutputCollision { .. }));
+        assert!(
+            error.to_string().contains("https://b.com/y/file.zip"),
+            "{}",
+            error
+        );
+        assert_eq!(rejected[1].status, TaskStatus::Skipped);
+        assert!(matches!(
+            rejected[1].error,
+            Some(DownloadError::DuplicateTask { id: 1, .. })
+        ));
+
+        let (outputs, rejected) = build(CollisionPolicy::Rename);
+        let statuses: Vec<_> = rejected
+            .iter()
+            .map(|outcome| (outcome.id, outcome.status))
+            .collect();
+        assert_eq!(statuses, [(3, TaskStatus::Skipped)], "{:#?}", rejected);
+        assert_eq!(
+            outputs,
+            [
+                PathBuf::from("file.zip"),
+                "./file (1).zip".into(),
+                "file (2).zip".into(),
+                "copy.zip".into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_tasks_must_agree() {
+        let task = |on_conflict, checksum: Option<&str>| {
+            silent_task("https://a.com/file.zip", "file.zip", on_conflict)
+                .with_checksum(checksum.map(|hex| Checksum::new(ChecksumAlgorithm::Md5, hex).unwrap()))
+        };
+        let md5 = "0".repeat(32);
+
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(task(ConflictPolicy::Fail, Some(&md5)));
+        builder.add_download_task(task(ConflictPolicy::Fail, Some(&md5)));
+        builder.add_download_task(task(ConflictPolicy::Overwrite, Some(&md5)));
+        builder.add_download_task(task(ConflictPolicy::Fail, None));
+        let (downloader, rejected) = builder.build().unwrap();
+
+        assert_eq!(downloader.tasks.len(), 1);
+        let outcomes: Vec<_> = rejected
+            .iter()
+            .map(|outcome| (outcome.id, outcome.status, outcome.error.as_ref().unwrap()))
+            .collect();
+        assert!(
+            matches!(
+                outcomes.as_slice(),
+                [
+                    (2, TaskStatus::Skipped, DownloadError::DuplicateTask { id: 1, .. }),
+                    (3, TaskStatus::Failed, DownloadError::OutputCollision { .. }),
+                    (4, TaskStatus::Failed, DownloadError::OutputCollision { .. }),
+                ]
+            ),
+            "{:#?}",
+            rejected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_options() {
+        let routes = warp::path("redirect")
+            .map(|| warp::redirect::found(warp::http::Uri::from_static("/agent")))
+            .or(warp::path("agent")
+                .and(warp::header::<String>("user-agent"))
+                .map(|agent: String| agent));
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let download = |follow_redirects| async move {
+            let config = AppConfig {
+                general: Default::default(),
+                download: Arc::new(config::app::DownloadConfig {
+                    follow_redirects,
+                    user_agent: "test-agent/1.0".to_string(),
+                    ..Default::default()
+                }),
+                progress_bar: Default::default(),
+                output: Default::default(),
+            };
+            let output = temp_path(&format!("client-options-{}", follow_redirects));
+
+            let mut builder = DownloaderBuilder::try_from(&config).unwrap();
+            builder.add_task(
+                format!("http://{}/redirect", addr),
+                &output,
+                ConflictPolicy::Fail,
+                Arc::new(Mutex::new(SilentReporter)),
+            );
+            let result = builder.build().unwrap().0.download_all().await;
+            let content = std::fs::read_to_string(&output).ok();
+            std::fs::remove_file(&output).ok();
+            (result, content)
+        };
+
+        let (result, content) = download(true).await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert_eq!(content.as_deref(), Some("test-agent/1.0"));
+
+        let (result, _) = download(false).await;
+        assert_eq!(result.errors().len(), 1, "{:#?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_request_headers() {
+        let routes = warp::path("headers")
+            .and(warp::header::<String>("authorization"))
+            .and(warp::header::<String>("x-run"))
+            .map(|auth: String, run: String| format!("{} {}", auth, run));
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, auth::bearer_auth("common").unwrap());
+        headers.insert("x-run", header::HeaderValue::from_static("run"));
+        let mut task_headers = HeaderMap::new();
+        task_headers.insert(header::AUTHORIZATION, auth::bearer_auth("task").unwrap());
+
+        let common = temp_path("headers-common");
+        let task = temp_path("headers-task");
+        let mut builder = DownloaderBuilder::new().with_headers(headers);
+        builder
+            .add_task(
+                format!("http://{}/headers", addr),
+                &common,
+                ConflictPolicy::Overwrite,
+                Arc::new(Mutex::new(SilentReporter)),
+            )
+            .add_download_task(
+                silent_task(
+                    &format!("http://{}/headers", addr),
+                    &task,
+                    ConflictPolicy::Overwrite,
+                )
+                .with_headers(task_headers),
+            )
+            .add_task(
+                format!("http://user:secret@{}/missing", addr),
+                temp_path("headers-missing"),
+                ConflictPolicy::Overwrite,
+                Arc::new(Mutex::new(SilentReporter)),
+            );
+        let result = builder.build().unwrap().0.download_all().await;
+
+        assert_eq!(std::fs::read_to_string(&common).unwrap(), "Bearer common run");
+        assert_eq!(std::fs::read_to_string(&task).unwrap(), "Bearer task run");
+        std::fs::remove_file(&common).ok();
+        std::fs::remove_file(&task).ok();
+
+        // Credentials of the URL are hidden from the errors
+        assert_eq!(result.errors().len(), 1, "{:#?}", result);
+        let error = format!("{:#}", result.errors()[0]);
+        assert!(error.contains("***@"), "{}", error);
+        assert!(!error.contains("secret"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_netrc_credentials() {
+        let routes = warp::path("netrc")
+            .and(warp::header::<String>("authorization"))
+            .map(|auth: String| auth);
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let netrc = Netrc::parse("machine 127.0.0.1 login user password secret").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, auth::bearer_auth("token").unwrap());
+
+        let from_netrc = temp_path("netrc");
+        let explicit = temp_path("netrc-explicit");
+        let mut builder = DownloaderBuilder::new().with_netrc(netrc);
+        builder
+            .add_task(
+                format!("http://{}/netrc", addr),
+                &from_netrc,
+                ConflictPolicy::Overwrite,
+                Arc::new(Mutex::new(SilentReporter)),
+            )
+            .add_download_task(
+                silent_task(
+                    &format!("http://{}/netrc", addr),
+                    &explicit,
+                    ConflictPolicy::Overwrite,
+                )
+                .with_headers(headers),
+            );
+        let result = builder.build().unwrap().0.download_all().await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+
+        assert_eq!(
+            std::fs::read_to_string(&from_netrc).unwrap(),
+            "Basic dXNlcjpzZWNyZXQ="
+        );
+        assert_eq!(std::fs::read_to_string(&explicit).unwrap(), "Bearer token");
+        std::fs::remove_file(&from_netrc).ok();
+        std::fs::remove_file(&explicit).ok();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        static CONTENT: [u8; 40_000] = [3u8; 40_000];
+        let (addr, _) = serve_ranges(&CONTENT, "\"v1\"");
+        let url = format!("http://{}/file.bin", addr);
+
+        // Two tasks share the limit: one second of burst, then another second for the rest
+        let outputs = [temp_path("rate-limit-1"), temp_path("rate-limit-2")];
+        let mut builder = DownloaderBuilder::new().with_rate_limit(40_000);
+        for output in &outputs {
+            builder.add_task(
+                &url,
+                output,
+                ConflictPolicy::Overwrite,
+                Arc::new(Mutex::new(SilentReporter)),
+            );
+        }
+        let downloader = builder.build().unwrap().0;
+        assert_eq!(downloader.rate_limiter().rate(), 40_000);
+
+        let start = std::time::Instant::now();
+        let result = downloader.download_all().await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert!(
+            start.elapsed() >= Duration::from_millis(800),
+            "{:?}",
+            start.elapsed()
+        );
+
+        // The limit of the task applies on top of the unlimited downloader
+        let mut builder = DownloaderBuilder::new();
+        builder.add_download_task(
+            silent_task(&url, &outputs[0], ConflictPolicy::Overwrite).with_rate_limit(20_000),
+        );
+        let start = std::time::Instant::now();
+        let result = builder.build().unwrap().0.download_all().await;
+        assert!(result.errors().is_empty(), "{:#?}", result);
+        assert!(
+            start.elapsed() >= Duration::from_millis(800),
+            "{:?}",
+            start.elapsed()
+        );
+
+        for output in &outputs {
+            assert_eq!(std::fs::read(output).unwrap(), CONTENT);
+            std::fs::remove_file(output).ok();
+        }
     }
 }
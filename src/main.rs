@@ -1,44 +1,91 @@
 use std::{
-    fmt::Display,
-    io::BufRead,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use tokio::sync::Mutex;
+use reqwest::header::{self, HeaderMap};
+use tokio::{signal, sync::Mutex};
+use tokio_util::sync::CancellationToken;
 
 use downloader_cli::{
-    DownloadResult, DownloadTask, Downloader,
-    builder::DownloaderBuilder,
-    config::{CliConfig, LogLevel, load_config},
+    DownloadResult, DownloadTask, Downloader, TaskOutcome, TaskStatus,
+    auth::{self, netrc::Netrc},
+    builder::{DownloaderBuilder, build_client},
+    checksum::manifest::ChecksumManifest,
+    config::{CliConfig, LogLevel, OutputFormat, load_config},
+    conflict::ConflictPolicy,
+    error::DownloadError,
+    exit_code::ExitCode,
+    input::InputFormat,
+    report::{self, ReportFormat},
     reporter::{
         DownloadReporter, ProgramFlowReporter, ReporterFactory, console_reporter::ConsoleReporterFactory,
-        program_flow::ProgramReporter,
+        json_reporter::JsonReporterFactory, program_flow::ProgramReporter,
     },
 };
 
 type AppConfig = downloader_cli::config::app::AppConfig;
 
+/// The source that reads the list of downloads from stdin
+const STDIN_SOURCE: &str = "-";
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> std::process::ExitCode {
     let args = CliConfig::parse();
-    let config = load_config(&args)?;
-    run(args, config).await
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            // An unreadable config file is a config problem, not an I/O failure of the downloads
+            eprintln!("Error: {:?}", err);
+            return ExitCode::Config.into();
+        }
+    };
+
+    match run(args, config).await {
+        Ok(code) => code.into(),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from_fatal(&err).into()
+        }
+    }
 }
 
-async fn run(args: CliConfig, config: AppConfig) -> anyhow::Result<()> {
+async fn run(args: CliConfig, config: AppConfig) -> anyhow::Result<ExitCode> {
     // Initializing reporters based on the config
     let mut program_reporter = ProgramReporter::from(&config);
-    let reporter_factory = ConsoleReporterFactory::new(&config.progress_bar, &config.output);
-    let downloader = build_downloader(&args, &config, reporter_factory)?;
+    let manifest = match &args.checksum_file {
+        Some(source) => Some(load_checksum_manifest(source, &args, &config).await?),
+        None => None,
+    };
+    let (downloader, rejected) = match (config.general.output_format, &args.events_file) {
+        (OutputFormat::Text, None) => {
+            let factory = ConsoleReporterFactory::new(&config.progress_bar, &config.output);
+            build_downloader(&args, &config, factory, manifest)?
+        }
+        (OutputFormat::Text, Some(_)) => anyhow::bail!("--events-file requires --output-format jsonl"),
+        (OutputFormat::Jsonl, None) => {
+            build_downloader(&args, &config, JsonReporterFactory::stdout(), manifest)?
+        }
+        (OutputFormat::Jsonl, Some(path)) => {
+            build_downloader(&args, &config, JsonReporterFactory::create_file(path)?, manifest)?
+        }
+    };
+    stop_on_signals(downloader.cancellation_token().clone(), config.general.log_level);
 
     program_reporter.on_start();
 
-    // Performing the download
-    let result = execute_download(downloader, args.resume).await;
-    handle_result(result, &config, &mut program_reporter)
+    // Performing the download, the rejected tasks are reported with the others
+    let mut result = execute_download(downloader, args.resume).await;
+    result.add_rejected(rejected);
+    if let Some(path) = &args.report {
+        let format = args
+            .report_format
+            .unwrap_or_else(|| ReportFormat::from_path(path));
+        report::write(path, format, &result)?;
+    }
+    Ok(handle_result(result, &config, &mut program_reporter))
 }
 
 async fn execute_download(mut downloader: Downloader, resume: bool) -> DownloadResult {
@@ -53,15 +100,35 @@ fn handle_result<T: ProgramFlowReporter>(
     result: DownloadResult,
     config: &AppConfig,
     program_reporter: &mut T,
-) -> anyhow::Result<()> {
-    if !result.errors.is_empty() {
-        print_errors("Download errors", &result.errors, config.general.log_level);
+) -> ExitCode {
+    if !result.is_success() {
+        let cancelled = result.cancelled().count();
+        if cancelled > 0 && config.general.log_level.show_errors() {
+            eprintln!("Cancelled {} downloads, --resume continues them", cancelled);
+        }
+
+        // The rejected tasks were listed before the download
+        let errors: Vec<_> = result
+            .failed()
+            .filter(|outcome| !outcome.is_rejected())
+            .filter_map(|outcome| outcome.error.as_ref())
+            .collect();
+        print_errors("Download errors", &errors, config.general.log_level);
+        if config.general.log_level.show_errors()
+            && errors
+                .iter()
+                .any(|err| matches!(err, DownloadError::FileExists { .. }))
+        {
+            eprintln!("Use -f --force or --on-conflict to resolve existing files");
+        }
 
-        if config.general.log_level.show_summary() {
-            let success_count = result.total - result.errors.len();
-            println!("\nSuccessfully downloaded {} files", success_count);
+        if config.general.log_level.show_summary() && config.general.output_format == OutputFormat::Text {
+            println!("\nSuccessfully downloaded {} files", result.succeeded().count());
         }
-        anyhow::bail!("Some downloads failed");
+        if result.failed().next().is_some() && config.general.log_level.show_errors() {
+            eprintln!("Error: Some downloads failed");
+        }
+        return ExitCode::from_result(&result);
     }
 
     if config.general.log_level.show_success() {
@@ -70,92 +137,261 @@ fn handle_result<T: ProgramFlowReporter>(
 
     program_reporter.on_finish();
 
-    Ok(())
+    ExitCode::Success
+}
+
+/// Cancels the download on the first `SIGINT` (Ctrl+C) or `SIGTERM` and exits on the second one
+fn stop_on_signals(cancellation: CancellationToken, log_level: LogLevel) {
+    tokio::spawn(async move {
+        if shutdown_signal().await.is_err() {
+            return;
+        }
+        if log_level.show_errors() {
+            eprintln!("Stopping the downloads, press Ctrl+C again to exit immediately");
+        }
+        cancellation.cancel();
+
+        if shutdown_signal().await.is_ok() {
+            std::process::exit(ExitCode::Interrupted.code().into());
+        }
+    });
+}
+
+/// Waits for `SIGINT` or, on Unix, `SIGTERM`
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c().await
 }
 
-fn build_downloader<F>(args: &CliConfig, config: &AppConfig, factory: F) -> Result<Downloader>
+fn build_downloader<F>(
+    args: &CliConfig,
+    config: &AppConfig,
+    factory: F,
+    manifest: Option<ChecksumManifest>,
+) -> Result<(Downloader, Vec<TaskOutcome>)>
 where
     F: ReporterFactory + Send + Sync + 'static,
     F::Reporter: DownloadReporter + Send + Sync + 'static,
 {
-    let destination = args
-        .target
-        .as_ref()
-        .or_else(|| config.download.download_dir.as_ref());
-
-    let mut builder = DownloaderBuilder::from(config);
-
-    // Processing the source (URL or file)
-    if Downloader::is_valid_url(&args.source) {
-        builder.add_task(
-            &args.source,
-            destination.unwrap_or(&PathBuf::from(DownloadTask::sanitize_filename(&args.source))),
-            args.force,
-            Arc::from(Mutex::new(factory.create())),
-        );
-    } else {
-        add_tasks_from_file(
-            &args.source,
-            &mut builder,
-            factory,
-            destination.unwrap_or(&PathBuf::from(".")),
-            args.force,
-        )?;
+    let destination = config
+        .download
+        .download_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let mut builder = DownloaderBuilder::try_from(config)?
+        .with_required_checksum(args.require_checksum)
+        .with_headers(request_headers(args)?);
+    if let Some(netrc) = load_netrc(config)? {
+        builder = builder.with_netrc(netrc);
+    }
+    if let Some(manifest) = manifest {
+        builder = builder.with_checksum_manifest(manifest);
     }
 
-    // Building a downloader and handling validation errors
-    let (downloader, validation_errors) = builder.build()?;
-    if !validation_errors.is_empty() {
-        print_errors("Validation errors", &validation_errors, config.general.log_level);
+    let single_url = matches!(args.sources.as_slice(), [source] if Downloader::is_valid_url(source));
+    if args.output.is_some() && !single_url {
+        anyhow::bail!("--output can only be used with a single URL, use --dir for several sources");
+    }
+    if args.checksum.is_some() && !single_url {
+        anyhow::bail!("--checksum can only be used with a single URL, add checksums to the source file");
+    }
+    let stdin_count = args
+        .sources
+        .iter()
+        .filter(|source| *source == STDIN_SOURCE)
+        .count();
+    if stdin_count > 1 {
+        anyhow::bail!("Stdin ('{}') can only be used as a source once", STDIN_SOURCE);
     }
 
-    Ok(downloader)
+    // Processing the sources (URLs, files and stdin)
+    let name_from_response = config.download.content_disposition;
+    for source in &args.sources {
+        if Downloader::is_valid_url(source) {
+            let output = match &args.output {
+                Some(output) => output.clone(),
+                None => destination.join(DownloadTask::sanitize_filename(source)),
+            };
+            builder.add_download_task(
+                DownloadTask::new(
+                    source,
+                    output,
+                    config.download.on_conflict,
+                    Arc::from(Mutex::new(factory.create())),
+                )
+                .with_checksum(args.checksum.clone())
+                .with_name_from_response(name_from_response && args.output.is_none()),
+            );
+        } else {
+            add_tasks_from_source(
+                source,
+                args.input_format,
+                &mut builder,
+                &factory,
+                &destination,
+                config.download.on_conflict,
+                name_from_response,
+            )?;
+        }
+    }
+
+    // Building a downloader and listing the rejected tasks
+    let (downloader, rejected) = builder.build()?;
+    let validation_errors: Vec<_> = rejected
+        .iter()
+        .filter(|outcome| outcome.status == TaskStatus::Failed)
+        .filter_map(|outcome| outcome.error.as_ref())
+        .collect();
+    print_errors("Validation errors", &validation_errors, config.general.log_level);
+
+    Ok((downloader, rejected))
+}
+
+/// Collects the headers of every request from `--header`, `--user` and `--bearer[-file]`
+fn request_headers(args: &CliConfig) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    for header in &args.headers {
+        let (name, value) = auth::parse_header(header)?;
+        headers.append(name, value);
+    }
+
+    if let Some(credentials) = &args.user {
+        let (user, password) = match credentials.split_once(':') {
+            Some((user, password)) => (user, Some(password)),
+            None => (credentials.as_str(), None),
+        };
+        headers.insert(header::AUTHORIZATION, auth::basic_auth(user, password));
+    }
+
+    let token = match &args.bearer_file {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read bearer token file: {}", path.display()))?,
+        ),
+        None => args.bearer.clone(),
+    };
+    if let Some(token) = token {
+        headers.insert(header::AUTHORIZATION, auth::bearer_auth(&token)?);
+    }
+
+    Ok(headers)
+}
+
+/// Loads the `.netrc` file from the config, otherwise from the default location if it exists
+fn load_netrc(config: &AppConfig) -> Result<Option<Netrc>> {
+    match &config.download.netrc {
+        Some(path) => Netrc::load(path).map(Some),
+        None => Netrc::default_path()
+            .filter(|path| path.is_file())
+            .map(Netrc::load)
+            .transpose(),
+    }
 }
 
-/// Reads a list of URLs from a file separated by newlines
-/// and adds them to the downloader as tasks.
+/// Loads the checksum manifest from a local file or downloads it with the headers and credentials
+/// of the downloads
+async fn load_checksum_manifest(
+    source: &str,
+    args: &CliConfig,
+    config: &AppConfig,
+) -> Result<ChecksumManifest> {
+    let content = if Downloader::is_valid_url(source) {
+        let mut headers = request_headers(args)?;
+        if !headers.contains_key(header::AUTHORIZATION)
+            && let Some(authorization) = load_netrc(config)?.and_then(|netrc| netrc.authorization(source))
+        {
+            headers.insert(header::AUTHORIZATION, authorization);
+        }
+        let url = auth::redact_url(source);
+
+        build_client(config)?
+            .get(source)
+            .headers(headers)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(reqwest::Error::without_url)
+            .with_context(|| format!("Failed to download checksum file: {}", url))?
+            .text()
+            .await
+            .with_context(|| format!("Failed to read checksum file: {}", url))?
+    } else {
+        std::fs::read_to_string(source)
+            .with_context(|| format!("Failed to read checksum file: {}", source))?
+    };
+
+    ChecksumManifest::parse(&content, source)
+}
+
+/// Reads a list of downloads from a file or stdin (`-`) and adds them to the downloader as tasks.
 ///
-/// `destination` is the directory where the files will be saved.
-fn add_tasks_from_file<F>(
-    file: impl AsRef<Path> + Display,
+/// The format is detected by the extension of the file unless `format` is set, stdin is read as a list.
+/// `destination` is the directory where the files will be saved, entries without an explicit
+/// output are named by the response if `name_from_response` is set.
+/// Invalid entries are added to the builder as rejected tasks.
+fn add_tasks_from_source<F>(
+    source: &str,
+    format: Option<InputFormat>,
     builder: &mut DownloaderBuilder,
-    reporter_factory: F,
-    destination: &PathBuf,
-    overwrite: bool,
+    reporter_factory: &F,
+    destination: &Path,
+    on_conflict: ConflictPolicy,
+    name_from_response: bool,
 ) -> anyhow::Result<()>
 where
     F: ReporterFactory + Send + Sync + 'static,
     F::Reporter: DownloadReporter + Send + Sync + 'static,
 {
-    if !destination.is_dir() {
-        return Err(anyhow::anyhow!("Destination path is not a directory"));
-    }
-
-    let file = std::fs::File::open(&file).with_context(|| format!("Failed to open source file: {}", file))?;
-    let reader = std::io::BufReader::new(file);
-
-    for (line_num, line) in reader.lines().enumerate() {
-        let url = line.with_context(|| format!("Failed to read line {} from source file", line_num + 1))?;
+    let (name, content, format) = if source == STDIN_SOURCE {
+        let content =
+            std::io::read_to_string(std::io::stdin()).context("Failed to read sources from stdin")?;
+        ("<stdin>", content, format.unwrap_or(InputFormat::List))
+    } else {
+        let content = std::fs::read_to_string(source)
+            .with_context(|| format!("Failed to read source file: {}", source))?;
+        (
+            source,
+            content,
+            format.unwrap_or_else(|| InputFormat::from_path(source)),
+        )
+    };
+    let (entries, errors) = format.parse(&content);
 
-        if !url.trim().is_empty() {
-            builder.add_task(
-                &url,
-                destination.join(DownloadTask::sanitize_filename(&url)),
-                overwrite,
-                Arc::from(Mutex::new(reporter_factory.create())),
-            );
-        }
+    for entry in entries {
+        let named = entry.output.is_some();
+        builder.add_download_task(
+            entry
+                .into_task(
+                    destination,
+                    on_conflict,
+                    Arc::from(Mutex::new(reporter_factory.create())),
+                )
+                .with_name_from_response(name_from_response && !named),
+        );
+    }
+    for err in errors {
+        builder.add_invalid_entry(err.context(name.to_string()));
     }
     Ok(())
 }
 
 /// Prints errors based on silent mode
-fn print_errors(title: &str, errors: &[anyhow::Error], log_level: LogLevel) {
+fn print_errors<E: std::fmt::Display>(title: &str, errors: &[E], log_level: LogLevel) {
     if !errors.is_empty() && log_level.show_errors() {
         eprintln!("{} ({}):", title, errors.len());
 
         for err in errors {
-            eprintln!("  - {}", err);
+            eprintln!("  - {:#}", err);
         }
     }
 }
@@ -0,0 +1,153 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::time::Instant;
+
+/// Token bucket limiting the download speed.
+///
+/// Clones share the bucket, so one limiter throttles the aggregate throughput of all the downloads
+/// using it, and its rate can be changed while they are running.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second, `0` is unlimited
+    rate: u64,
+    /// Available bytes, negative when the consumers are in debt
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RateLimiter {
+    /// `bytes_per_sec` of `0` means no limit
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate: bytes_per_sec,
+                tokens: bytes_per_sec as f64,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().unwrap().rate
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.rate() == 0
+    }
+
+    /// Changes the limit, affecting the downloads in progress as well
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        bucket.rate = bytes_per_sec;
+        bucket.tokens = bucket.tokens.min(bytes_per_sec as f64);
+    }
+
+    /// Takes `bytes` from the bucket, waiting until the rate allows them
+    pub async fn acquire(&self, bytes: u64) {
+        let delay = self.bucket.lock().unwrap().take(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+impl Bucket {
+    /// Adds the tokens earned since the last update, allowing bursts of up to one second
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.updated).as_secs_f64() * self.rate as f64;
+        self.tokens = (self.tokens + earned).min(self.rate as f64);
+        self.updated = now;
+    }
+
+    /// Returns how long the consumer has to wait to pay off the debt
+    fn take(&mut self, bytes: u64) -> Duration {
+        if self.rate == 0 {
+            return Duration::ZERO;
+        }
+
+        self.refill();
+        self.tokens -= bytes as f64;
+
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / self.rate as f64)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Parses a rate in bytes per second with an optional binary suffix: `500K`, `2M`, `1.5G`
+pub fn parse_rate(rate: &str) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid rate: '{}', expected e.g. 500K or 2M", rate);
+
+    let rate = rate.trim();
+    let (number, multiplier) = match rate.char_indices().last().ok_or_else(invalid)? {
+        (i, 'k' | 'K') => (&rate[..i], 1024),
+        (i, 'm' | 'M') => (&rate[..i], 1024 * 1024),
+        (i, 'g' | 'G') => (&rate[..i], 1024 * 1024 * 1024),
+        _ => (rate, 1),
+    };
+
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid());
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("100").unwrap(), 100);
+        assert_eq!(parse_rate("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("2m").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("1.5G").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_rate("0").unwrap(), 0);
+
+        for rate in ["", "M", "fast", "-1K", "2T"] {
+            assert!(parse_rate(rate).is_err(), "{}", rate);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(10_000);
+
+        // The first second is a burst
+        let start = Instant::now();
+        limiter.acquire(10_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // Clones share the bucket
+        let start = Instant::now();
+        limiter.clone().acquire(2_000).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(180), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+
+        limiter.set_rate(0);
+        let start = Instant::now();
+        limiter.acquire(1_000_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
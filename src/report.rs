@@ -0,0 +1,330 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{DownloadResult, TaskOutcome, TaskStatus};
+
+/// Format of the report with the results of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// JSON object with the totals and an entry per task
+    Json,
+    /// CSV with a row per task
+    Csv,
+    /// JUnit XML with a test case per task
+    Junit,
+}
+
+impl ReportFormat {
+    /// Detects the format by the file extension, falling back to JSON
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") => Self::Csv,
+            Some("xml") => Self::Junit,
+            _ => Self::Json,
+        }
+    }
+
+    /// Renders the result of the run in the format
+    pub fn render(self, result: &DownloadResult) -> Result<String> {
+        let entries: Vec<_> = result.outcomes.iter().map(ReportEntry::from).collect();
+
+        match self {
+            Self::Json => Ok(serde_json::to_string_pretty(&JsonReport {
+                total: result.total,
+                succeeded: result.succeeded().count(),
+                skipped: result.skipped().count(),
+                failed: result.failed().count(),
+                cancelled: result.cancelled().count(),
+                tasks: entries,
+            })?),
+            Self::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                for entry in &entries {
+                    writer.serialize(entry)?;
+                }
+                Ok(String::from_utf8(writer.into_inner()?)?)
+            }
+            Self::Junit => Ok(junit(result)),
+        }
+    }
+}
+
+/// Writes the report of the run to the file
+pub fn write(path: &Path, format: ReportFormat, result: &DownloadResult) -> Result<()> {
+    std::fs::write(path, format.render(result)?)
+        .with_context(|| format!("Failed to write report: {}", path.display()))
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    total: usize,
+    succeeded: usize,
+    skipped: usize,
+    failed: usize,
+    cancelled: usize,
+    tasks: Vec<ReportEntry<'a>>,
+}
+
+/// A task in the report, flat to fit a CSV row
+#[derive(Serialize)]
+struct ReportEntry<'a> {
+    id: usize,
+    status: &'static str,
+    url: &'a str,
+    final_url: Option<&'a str>,
+    output: String,
+    http_status: Option<u16>,
+    bytes: u64,
+    duration_ms: u64,
+    /// Average speed in bytes per second
+    speed: u64,
+    checksum: Option<String>,
+    attempts: usize,
+    error: Option<String>,
+}
+
+impl<'a> From<&'a TaskOutcome> for ReportEntry<'a> {
+    fn from(outcome: &'a TaskOutcome) -> Self {
+        Self {
+            id: outcome.id,
+            status: outcome.status.name(),
+            url: &outcome.url,
+            final_url: outcome.final_url.as_deref(),
+            output: outcome.output.display().to_string(),
+            http_status: outcome.http_status.map(|status| status.as_u16()),
+            bytes: outcome.bytes,
+            duration_ms: outcome.elapsed.as_millis() as u64,
+            speed: outcome.speed() as u64,
+            checksum: outcome.checksum.as_ref().map(ToString::to_string),
+            attempts: outcome.attempts,
+            error: outcome.error.as_ref().map(|error| format!("{:#}", error)),
+        }
+    }
+}
+
+/// A test suite with a test case per task, failed downloads are failures,
+/// skipped and cancelled ones are skipped tests.
+///
+/// Test cases are named by the URL, or by the task number for invalid input entries without one.
+/// A failure has the innermost cause as the message and the whole error chain as the text.
+fn junit(result: &DownloadResult) -> String {
+    let failures = result.failed().count();
+    let skipped = result.skipped().count() + result.cancelled().count();
+    let time: f64 = result
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.elapsed.as_secs_f64())
+        .sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        "<testsuite name=\"downloads\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        result.outcomes.len(),
+        failures,
+        skipped,
+        time
+    )
+    .unwrap();
+
+    for outcome in &result.outcomes {
+        let name = match outcome.url.as_str() {
+            "" => format!("task {}", outcome.id),
+            url => url.to_string(),
+        };
+        write!(
+            xml,
+            "  <testcase classname=\"downloads\" name=\"{}\" time=\"{:.3}\">",
+            escape_xml(&name),
+            outcome.elapsed.as_secs_f64()
+        )
+        .unwrap();
+        match (outcome.status, &outcome.error) {
+            (TaskStatus::Failed, Some(error)) => write!(
+                xml,
+                "\n    <failure message=\"{}\">{}</failure>\n  ",
+                escape_xml(&error.root_cause()),
+                escape_xml(&format!("{:#}", error))
+            )
+            .unwrap(),
+            (TaskStatus::Skipped, Some(reason)) => write!(
+                xml,
+                "\n    <skipped message=\"{}\"/>\n  ",
+                escape_xml(&reason.to_string())
+            )
+            .unwrap(),
+            (TaskStatus::Skipped | TaskStatus::Cancelled, _) => {
+                write!(xml, "\n    <skipped message=\"{}\"/>\n  ", outcome.status).unwrap()
+            }
+            _ => {}
+        }
+        writeln!(
+            xml,
+            "<system-out>{} bytes to {}</system-out></testcase>",
+            outcome.bytes,
+            escape_xml(&outcome.output.display().to_string())
+        )
+        .unwrap();
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::error::DownloadError;
+
+    fn result() -> DownloadResult {
+        let succeeded = TaskOutcome {
+            id: 1,
+            status: TaskStatus::Succeeded,
+            url: "https://example.com/a.txt".to_string(),
+            final_url: Some("https://cdn.example.com/a.txt".to_string()),
+            output: "a.txt".into(),
+            http_status: Some(StatusCode::OK),
+            bytes: 2048,
+            elapsed: Duration::from_secs(2),
+            checksum: Some("md5:d41d8cd98f00b204e9800998ecf8427e".parse().unwrap()),
+            attempts: 1,
+            error: None,
+        };
+        let failed = TaskOutcome {
+            id: 2,
+            status: TaskStatus::Failed,
+            url: "https://example.com/b?x=1&y=<2>".to_string(),
+            final_url: None,
+            output: "b".into(),
+            http_status: Some(StatusCode::NOT_FOUND),
+            bytes: 0,
+            elapsed: Duration::from_millis(500),
+            checksum: None,
+            attempts: 2,
+            error: Some(DownloadError::HttpStatus {
+                status: StatusCode::NOT_FOUND,
+                error: anyhow::anyhow!("Request failed with status: 404 Not Found"),
+            }),
+        };
+        let skipped = TaskOutcome {
+            id: 3,
+            status: TaskStatus::Skipped,
+            url: "https://example.com/c.txt".to_string(),
+            final_url: None,
+            output: "c.txt".into(),
+            http_status: None,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            checksum: None,
+            attempts: 0,
+            error: None,
+        };
+
+        DownloadResult {
+            total: 3,
+            outcomes: vec![succeeded, failed, skipped],
+        }
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(ReportFormat::from_path("report.CSV"), ReportFormat::Csv);
+        assert_eq!(ReportFormat::from_path("junit.xml"), ReportFormat::Junit);
+        assert_eq!(ReportFormat::from_path("report"), ReportFormat::Json);
+    }
+
+    #[test]
+    fn test_json() {
+        let report: serde_json::Value =
+            serde_json::from_str(&ReportFormat::Json.render(&result()).unwrap()).unwrap();
+
+        assert_eq!(report["succeeded"], 1);
+        assert_eq!(report["skipped"], 1);
+        assert_eq!(report["failed"], 1);
+        assert_eq!(report["tasks"][0]["final_url"], "https://cdn.example.com/a.txt");
+        assert_eq!(report["tasks"][0]["speed"], 1024);
+        assert_eq!(
+            report["tasks"][0]["checksum"],
+            "md5:d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert_eq!(report["tasks"][1]["status"], "failed");
+        assert_eq!(report["tasks"][1]["http_status"], 404);
+        assert_eq!(report["tasks"][1]["attempts"], 2);
+    }
+
+    #[test]
+    fn test_csv() {
+        let report = ReportFormat::Csv.render(&result()).unwrap();
+        let lines: Vec<_> = report.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "id,status,url,final_url,output,http_status,bytes,duration_ms,speed,checksum,attempts,error"
+        );
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with("2,failed,"));
+        assert!(lines[2].ends_with(",2,Request failed with status: 404 Not Found"));
+    }
+
+    #[test]
+    fn test_junit() {
+        let mut result = result();
+        let error = anyhow::anyhow!("Invalid URL 'not a url'")
+            .context("Invalid entry on line 2")
+            .context("list.txt");
+        result.add_rejected(vec![TaskOutcome {
+            id: 4,
+            status: TaskStatus::Failed,
+            url: String::new(),
+            final_url: None,
+            output: "".into(),
+            http_status: None,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            checksum: None,
+            attempts: 0,
+            error: Some(DownloadError::InvalidEntry(error)),
+        }]);
+        let report = ReportFormat::Junit.render(&result).unwrap();
+
+        assert!(report.contains(
+            "<testsuite name=\"downloads\" tests=\"4\" failures=\"2\" skipped=\"1\" time=\"2.500\">"
+        ));
+        assert!(report.contains("name=\"https://example.com/b?x=1&amp;y=&lt;2&gt;\""));
+        assert!(report.contains("<failure message=\"Request failed with status: 404 Not Found\">"));
+        assert_eq!(report.matches("<failure").count(), 2);
+        assert!(report.contains("<skipped message=\"skipped\"/>"));
+        // The invalid entry has no URL, its message is the cause and the text is the whole chain
+        assert!(report.contains(
+            "name=\"task 4\" time=\"0.000\">\n    <failure message=\"Invalid URL &apos;not a url&apos;\">\
+             list.txt: Invalid entry on line 2: Invalid URL &apos;not a url&apos;</failure>"
+        ));
+    }
+}
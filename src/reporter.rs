@@ -1,19 +1,31 @@
-use std::path::Path;
+use std::{path::Path, time::Duration};
+
+use crate::{conflict::ConflictPolicy, error::DownloadError};
 
 pub mod console_reporter;
+pub mod json_reporter;
 pub mod program_flow;
 pub mod silent_reporter;
 
 pub trait DownloadReporter: Send + Sync {
+    /// Called before any other event with the number of the task, the same as [`crate::TaskOutcome::id`]
+    fn on_task_start(&mut self, id: usize);
     fn on_request(&mut self, url: &str);
     fn on_response(&mut self, response: &reqwest::Response);
-    fn on_file_exists(&mut self, path: &Path, overwrite: bool);
+    /// Called when the output exists, before the `policy` is applied
+    fn on_file_exists(&mut self, path: &Path, policy: ConflictPolicy);
     fn on_file_create(&mut self, path: &Path);
     fn on_file_size_known(&mut self, size: Option<u64>);
     fn on_start_download(&mut self, url: &str, file: &Path);
+    /// Called after `on_start_download` when the download continues a partial file of `offset` bytes
+    fn on_resume(&mut self, offset: u64);
     fn on_progress(&mut self, delta: u64);
     fn on_complete(&mut self, url: &str, path: &Path);
-    fn on_error(&mut self, error: &anyhow::Error);
+    /// Called before the `attempt`-th of `retries` repeated attempts, which starts after `delay`
+    fn on_retry(&mut self, attempt: usize, retries: usize, delay: Duration, error: &anyhow::Error);
+    /// Called when the download switches to the mirror `url` after the `error` of the previous one
+    fn on_mirror(&mut self, url: &str, error: &anyhow::Error);
+    fn on_error(&mut self, error: &DownloadError);
 }
 
 pub trait ReporterFactory {
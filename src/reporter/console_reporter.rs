@@ -1,12 +1,15 @@
 use std::{path::Path, sync::Arc, time::Duration};
 
-use anyhow::{Context, Result};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rand::{self, seq::IndexedRandom};
 use reqwest::Response;
 
 use super::{DownloadReporter, ReporterFactory};
-use crate::config::app::{OutputConfig, ProgressBarConfig};
+use crate::{
+    config::app::{OutputConfig, ProgressBarConfig},
+    conflict::ConflictPolicy,
+    error::DownloadError,
+};
 
 #[derive(Clone)]
 pub struct ConsoleReporterFactory {
@@ -60,6 +63,7 @@ pub struct ConsoleReporter {
     multi_progress: MultiProgress,
     progress_bar: Option<ProgressBar>,
     file_size: Option<u64>,
+    url: Option<String>,
     max_displayed_filename: usize,
     output_config: Arc<OutputConfig>,
 
@@ -73,6 +77,7 @@ pub struct ConsoleReporter {
 }
 
 impl ConsoleReporter {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         multi_progress: MultiProgress,
         max_displayed_filename: usize,
@@ -89,6 +94,7 @@ impl ConsoleReporter {
             max_displayed_filename,
             progress_bar: None,
             file_size: None,
+            url: None,
             progress_bar_template,
             progress_bar_chars,
             spinner_template,
@@ -122,6 +128,8 @@ impl ConsoleReporter {
 }
 
 impl DownloadReporter for ConsoleReporter {
+    fn on_task_start(&mut self, _id: usize) {}
+
     /// Create progress bar for request
     fn on_request(&mut self, url: &str) {
         let pb = self.multi_progress.add(
@@ -136,40 +144,77 @@ impl DownloadReporter for ConsoleReporter {
         );
         pb.enable_steady_tick(Duration::from_millis(100));
         self.progress_bar.replace(pb);
+        self.url = Some(url.to_string());
     }
 
-    fn on_response(&mut self, response: &Response) {
+    fn on_response(&mut self, _response: &Response) {
         if let Some(pb) = &self.progress_bar {
             pb.finish_and_clear()
         }
         Self::println(&self.output_config.message_on_response);
     }
 
-    fn on_file_exists(&mut self, path: &Path, overwrite: bool) {
-        if !overwrite {
-            println!("File exists: {}. See '--help' for solutions.", path.display());
+    fn on_file_exists(&mut self, path: &Path, policy: ConflictPolicy) {
+        match policy {
+            ConflictPolicy::Fail => println!("File exists: {}. See '--help' for solutions.", path.display()),
+            ConflictPolicy::Skip => println!("File exists, skipping: {}", path.display()),
+            ConflictPolicy::Overwrite | ConflictPolicy::Rename | ConflictPolicy::Backup => {}
         }
     }
 
-    fn on_complete(&mut self, url: &str, path: &Path) {
+    fn on_complete(&mut self, _url: &str, _path: &Path) {
         if let Some(pb) = &self.progress_bar {
             pb.finish();
             self.progress_bar = None
         }
     }
 
-    fn on_error(&mut self, error: &anyhow::Error) {
+    /// Clear the failed progress bar and show when the next attempt starts
+    fn on_retry(&mut self, attempt: usize, retries: usize, delay: Duration, error: &anyhow::Error) {
+        if let Some(pb) = self.progress_bar.take() {
+            pb.finish_and_clear();
+        }
+
+        self.multi_progress
+            .println(format!(
+                "Retry {}/{} in {:.0?} for {}: {:#}",
+                attempt,
+                retries,
+                delay,
+                self.url.as_deref().unwrap_or_default(),
+                error
+            ))
+            .ok();
+    }
+
+    /// Clear the failed progress bar and show which mirror is used next
+    fn on_mirror(&mut self, url: &str, error: &anyhow::Error) {
+        if let Some(pb) = self.progress_bar.take() {
+            pb.finish_and_clear();
+        }
+
+        self.multi_progress
+            .println(format!(
+                "Mirror {} failed: {:#}. Trying {}",
+                self.url.as_deref().unwrap_or_default(),
+                error,
+                url
+            ))
+            .ok();
+    }
+
+    fn on_error(&mut self, error: &DownloadError) {
         println!("{}", error);
     }
 
     fn on_file_size_known(&mut self, size: Option<u64>) {
         self.file_size = size;
-        if let Some(size) = size {
-            // println!("Size: {}", indicatif::HumanBytes(size));
-        }
+        // if let Some(size) = size {
+        //     println!("Size: {}", indicatif::HumanBytes(size));
+        // }
     }
 
-    fn on_file_create(&mut self, path: &Path) {
+    fn on_file_create(&mut self, _path: &Path) {
         // println!("Saving as: {}", path.display());
     }
 
@@ -181,7 +226,7 @@ impl DownloadReporter for ConsoleReporter {
     }
 
     /// Setup progress bar for download
-    fn on_start_download(&mut self, url: &str, file: &Path) {
+    fn on_start_download(&mut self, _url: &str, file: &Path) {
         let pb = if let Some(size) = self.file_size {
             ProgressBar::new(size).with_style(
                 ProgressStyle::with_template(&self.progress_bar_template)
@@ -200,4 +245,12 @@ impl DownloadReporter for ConsoleReporter {
         let pb = self.multi_progress.add(pb);
         self.progress_bar = Some(pb);
     }
+
+    /// Move the progress bar to the already downloaded part
+    fn on_resume(&mut self, offset: u64) {
+        if let Some(pb) = &self.progress_bar {
+            pb.set_position(offset);
+            pb.reset_eta();
+        }
+    }
 }
@@ -0,0 +1,278 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use reqwest::{
+    Response,
+    header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderName, LAST_MODIFIED},
+};
+use serde_json::{Map, Value, json};
+
+use super::{DownloadReporter, ReporterFactory};
+use crate::{conflict::ConflictPolicy, error::DownloadError};
+
+/// Minimal interval between two progress events of a task
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Response headers of the `response` events, the others may carry cookies or tokens
+const RESPONSE_HEADERS: [HeaderName; 5] = [CONTENT_LENGTH, CONTENT_TYPE, CONTENT_RANGE, ETAG, LAST_MODIFIED];
+
+type SharedWriter = Arc<std::sync::Mutex<Box<dyn Write + Send>>>;
+
+/// Creates reporters writing JSON Lines events to one stream
+pub struct JsonReporterFactory {
+    writer: SharedWriter,
+}
+
+impl ReporterFactory for JsonReporterFactory {
+    fn create(&self) -> Self::Reporter {
+        JsonReporter {
+            id: 0,
+            writer: self.writer.clone(),
+            url: None,
+            output: None,
+            size: None,
+            downloaded: 0,
+            last_progress: None,
+        }
+    }
+
+    type Reporter = JsonReporter;
+}
+
+impl JsonReporterFactory {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(std::sync::Mutex::new(Box::new(writer))),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+
+    /// Writes the events to the file, replacing its content
+    pub fn create_file(path: &Path) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create events file: {}", path.display()))?;
+        Ok(Self::new(std::io::LineWriter::new(file)))
+    }
+}
+
+/// Reports the events of a task as JSON objects, one per line:
+///
+/// ```json
+/// {"event":"progress","task":1,"url":"https://example.com/a.iso","output":"a.iso","time_ms":1760000000000,"downloaded":1048576,"size":4194304}
+/// ```
+pub struct JsonReporter {
+    /// Number of the task, set when it starts
+    id: usize,
+    writer: SharedWriter,
+    url: Option<String>,
+    output: Option<PathBuf>,
+    size: Option<u64>,
+    downloaded: u64,
+    last_progress: Option<Instant>,
+}
+
+impl JsonReporter {
+    /// Writes the event with the task fields followed by `fields`
+    fn emit(&self, event: &str, fields: Value) {
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+
+        let mut object = Map::new();
+        object.insert("event".into(), event.into());
+        object.insert("task".into(), self.id.into());
+        object.insert("url".into(), json!(self.url));
+        object.insert(
+            "output".into(),
+            json!(self.output.as_ref().map(|path| path.display().to_string())),
+        );
+        object.insert("time_ms".into(), time_ms.into());
+        if let Value::Object(fields) = fields {
+            object.extend(fields);
+        }
+
+        // A broken events stream must not fail the downloads
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        let line = Value::Object(object).to_string();
+        writeln!(writer, "{}", line).and_then(|_| writer.flush()).ok();
+    }
+}
+
+impl DownloadReporter for JsonReporter {
+    fn on_task_start(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    fn on_request(&mut self, url: &str) {
+        self.url = Some(url.to_string());
+        self.emit("request", json!({}));
+    }
+
+    fn on_response(&mut self, response: &Response) {
+        let mut headers = Map::new();
+        for name in RESPONSE_HEADERS {
+            let values: Vec<_> = response
+                .headers()
+                .get_all(&name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .collect();
+            if !values.is_empty() {
+                headers.insert(name.to_string(), values.join(", ").into());
+            }
+        }
+
+        self.emit(
+            "response",
+            json!({ "status": response.status().as_u16(), "headers": headers }),
+        );
+    }
+
+    fn on_file_exists(&mut self, path: &Path, policy: ConflictPolicy) {
+        self.output = Some(path.to_path_buf());
+        self.emit("file_exists", json!({ "policy": policy }));
+    }
+
+    fn on_file_create(&mut self, path: &Path) {
+        self.output = Some(path.to_path_buf());
+    }
+
+    fn on_file_size_known(&mut self, size: Option<u64>) {
+        self.size = size;
+        self.emit("size", json!({ "size": size }));
+    }
+
+    fn on_start_download(&mut self, url: &str, file: &Path) {
+        self.url = Some(url.to_string());
+        self.output = Some(file.to_path_buf());
+        self.downloaded = 0;
+        self.last_progress = None;
+        self.emit("start", json!({ "size": self.size }));
+    }
+
+    fn on_resume(&mut self, offset: u64) {
+        self.downloaded = offset;
+        self.emit("resume", json!({ "offset": offset }));
+    }
+
+    /// Reports the progress at most once per [`PROGRESS_INTERVAL`]
+    fn on_progress(&mut self, delta: u64) {
+        self.downloaded += delta;
+        if self
+            .last_progress
+            .is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL)
+        {
+            self.last_progress = Some(Instant::now());
+            self.emit(
+                "progress",
+                json!({ "downloaded": self.downloaded, "size": self.size }),
+            );
+        }
+    }
+
+    fn on_complete(&mut self, url: &str, path: &Path) {
+        self.url = Some(url.to_string());
+        self.output = Some(path.to_path_buf());
+        self.emit("complete", json!({ "downloaded": self.downloaded }));
+    }
+
+    fn on_retry(&mut self, attempt: usize, retries: usize, delay: Duration, error: &anyhow::Error) {
+        self.emit(
+            "retry",
+            json!({
+                "attempt": attempt,
+                "retries": retries,
+                "delay_ms": delay.as_millis() as u64,
+                "error": format!("{:#}", error),
+            }),
+        );
+    }
+
+    fn on_mirror(&mut self, url: &str, error: &anyhow::Error) {
+        self.emit(
+            "mirror",
+            json!({ "mirror": url, "error": format!("{:#}", error) }),
+        );
+    }
+
+    fn on_error(&mut self, error: &DownloadError) {
+        self.emit("error", json!({ "error": format!("{:#}", error) }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer whose content stays readable after the factory takes it
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let buffer = Buffer::default();
+        let factory = JsonReporterFactory::new(buffer.clone());
+        let mut first = factory.create();
+        let mut second = factory.create();
+        // The tasks may start in a different order than the reporters were created
+        second.on_task_start(2);
+        first.on_task_start(1);
+
+        first.on_request("https://example.com/a.txt");
+        first.on_file_size_known(Some(10));
+        first.on_start_download("https://example.com/a.txt", Path::new("a.txt"));
+        first.on_progress(4);
+        first.on_progress(6);
+        first.on_complete("https://example.com/a.txt", Path::new("a.txt"));
+        second.on_request("https://example.com/b.txt");
+        second.on_error(&DownloadError::Other(anyhow::anyhow!("Not found")));
+
+        let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| (event["event"].as_str().unwrap(), event["task"].as_u64().unwrap()))
+            .collect();
+
+        // The second progress event is within the interval of the first one
+        assert_eq!(
+            summary,
+            [
+                ("request", 1),
+                ("size", 1),
+                ("start", 1),
+                ("progress", 1),
+                ("complete", 1),
+                ("request", 2),
+                ("error", 2),
+            ]
+        );
+        assert_eq!(events[3]["downloaded"], 4);
+        assert_eq!(events[4]["downloaded"], 10);
+        assert_eq!(events[4]["output"], "a.txt");
+        assert_eq!(events[6]["url"], "https://example.com/b.txt");
+        assert_eq!(events[6]["output"], Value::Null);
+        assert_eq!(events[6]["error"], "Not found");
+    }
+}
@@ -1,17 +1,20 @@
 use std::sync::Arc;
 
-use crate::config::app::{AppConfig, LogLevel, OutputConfig};
+use crate::config::app::{AppConfig, OutputConfig, OutputFormat};
 
 use super::ProgramFlowReporter;
 
 pub struct ProgramReporter {
-    log_level: LogLevel,
+    output_format: OutputFormat,
     config: Arc<OutputConfig>,
 }
 
 impl ProgramReporter {
+    /// Messages are only printed along with the text output, so they don't break the events
     fn print_message(&self, message: &Option<String>) {
-        if let Some(message) = message {
+        if self.output_format == OutputFormat::Text
+            && let Some(message) = message
+        {
             println!("{}", message);
         }
     }
@@ -26,7 +29,7 @@ impl ProgramFlowReporter for ProgramReporter {
         self.print_message(&self.config.message_on_finish);
     }
 
-    fn on_errors(&mut self, errors: Vec<anyhow::Error>) {
+    fn on_errors(&mut self, _errors: Vec<anyhow::Error>) {
         // TODO: Unimplemented
     }
 
@@ -38,7 +41,7 @@ impl ProgramFlowReporter for ProgramReporter {
 impl From<&AppConfig> for ProgramReporter {
     fn from(value: &AppConfig) -> Self {
         Self {
-            log_level: value.general.log_level,
+            output_format: value.general.output_format,
             config: Arc::clone(&value.output),
         }
     }
@@ -1,4 +1,5 @@
 use super::{DownloadReporter, ReporterFactory};
+use crate::{conflict::ConflictPolicy, error::DownloadError};
 
 pub struct SilentReporterFactory;
 
@@ -12,21 +13,36 @@ impl ReporterFactory for SilentReporterFactory {
 pub struct SilentReporter;
 
 impl DownloadReporter for SilentReporter {
-    fn on_request(&mut self, url: &str) {}
+    fn on_task_start(&mut self, _id: usize) {}
 
-    fn on_response(&mut self, response: &reqwest::Response) {}
+    fn on_request(&mut self, _url: &str) {}
 
-    fn on_file_exists(&mut self, path: &std::path::Path, overwrite: bool) {}
+    fn on_response(&mut self, _response: &reqwest::Response) {}
 
-    fn on_file_create(&mut self, path: &std::path::Path) {}
+    fn on_file_exists(&mut self, _path: &std::path::Path, _policy: ConflictPolicy) {}
 
-    fn on_file_size_known(&mut self, size: Option<u64>) {}
+    fn on_file_create(&mut self, _path: &std::path::Path) {}
 
-    fn on_start_download(&mut self, url: &str, file: &std::path::Path) {}
+    fn on_file_size_known(&mut self, _size: Option<u64>) {}
 
-    fn on_progress(&mut self, delta: u64) {}
+    fn on_start_download(&mut self, _url: &str, _file: &std::path::Path) {}
 
-    fn on_complete(&mut self, url: &str, path: &std::path::Path) {}
+    fn on_resume(&mut self, _offset: u64) {}
 
-    fn on_error(&mut self, error: &anyhow::Error) {}
+    fn on_progress(&mut self, _delta: u64) {}
+
+    fn on_complete(&mut self, _url: &str, _path: &std::path::Path) {}
+
+    fn on_retry(
+        &mut self,
+        _attempt: usize,
+        _retries: usize,
+        _delay: std::time::Duration,
+        _error: &anyhow::Error,
+    ) {
+    }
+
+    fn on_mirror(&mut self, _url: &str, _error: &anyhow::Error) {}
+
+    fn on_error(&mut self, _error: &DownloadError) {}
 }
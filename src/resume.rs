@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use reqwest::{
+    Response,
+    header::{ETAG, HeaderValue, LAST_MODIFIED},
+};
+use serde::{Deserialize, Serialize};
+
+/// Suffix of the sidecar file that stores validators of a partial download
+const STATE_SUFFIX: &str = ".dw-resume";
+
+/// Validators of a partially downloaded file, used to build an `If-Range` header
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResumeState {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl ResumeState {
+    /// Collects validators from the response headers.
+    /// Returns `None` if the server provided neither `ETag` nor `Last-Modified`.
+    pub fn from_response(url: &str, response: &Response) -> Option<Self> {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        let state = Self {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        (state.etag.is_some() || state.last_modified.is_some()).then_some(state)
+    }
+
+    /// Returns the value for the `If-Range` header.
+    ///
+    /// A strong `ETag` is preferred, weak ones can't be used in `If-Range`.
+    pub fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    /// Checks that the response comes from the same version of the file, comparing its validator
+    /// with the stored one
+    pub fn matches(&self, response: &Response) -> bool {
+        Self::from_response(&self.url, response).is_some_and(|fresh| fresh.if_range() == self.if_range())
+    }
+
+    /// Path of the sidecar file for the `output` file
+    pub fn path_for(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(STATE_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// Loads the saved state, `None` if there is no state or it can't be parsed
+    pub async fn load(output: &Path) -> Option<Self> {
+        let content = tokio::fs::read_to_string(Self::path_for(output)).await.ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    pub async fn save(&self, output: &Path) -> Result<()> {
+        let path = Self::path_for(output);
+        let content = toml::to_string(self)?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to save resume state: {}", path.display()))
+    }
+
+    /// Removes the saved state if it exists
+    pub async fn remove(output: &Path) -> Result<()> {
+        let path = Self::path_for(output);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove resume state: {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses the `Content-Range: bytes start-end/total` header.
+///
+/// Returns `(start, end, total)`, where `total` is `None` for `*`.
+pub fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (range, total) = range.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+
+    Some((start.trim().parse().ok()?, end.trim().parse().ok()?, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 0-99/100"), Some((0, 99, Some(100))));
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, 199, None)));
+        assert_eq!(parse_content_range("bytes */100"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+
+    #[test]
+    fn test_if_range_prefers_strong_etag() {
+        let mut state = ResumeState {
+            url: "https://example.com/file".to_string(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        };
+        assert_eq!(state.if_range(), Some("\"abc\""));
+
+        state.etag = Some("W/\"abc\"".to_string());
+        assert_eq!(state.if_range(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+        state.last_modified = None;
+        assert_eq!(state.if_range(), None);
+    }
+
+    #[test]
+    fn test_state_path() {
+        assert_eq!(
+            ResumeState::path_for(Path::new("dir/file.zip")),
+            PathBuf::from("dir/file.zip.dw-resume")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::{StatusCode, header::HeaderMap};
+
+use crate::{checksum::ChecksumMismatch, config::app::RETRIES};
+
+/// Controls how many times and how long to wait before repeating a failed download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of repeated attempts after the first one
+    pub retries: usize,
+    /// Delay before the first retry, doubled on every next one
+    pub base_delay: Duration,
+    /// Upper bound of the exponential delay and of the server's `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: RETRIES,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(retries: usize) -> Self {
+        Self {
+            retries,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Returns the delay before the `attempt`-th retry (starting from 1).
+    ///
+    /// The server's `Retry-After` takes precedence over the exponential backoff,
+    /// but is still capped by `max_delay`.
+    pub fn delay(&self, attempt: usize, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+
+        // "Equal jitter": half of the delay is fixed, the other half is random
+        let half = backoff / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// A non-success HTTP response
+#[derive(Debug)]
+pub struct StatusError {
+    pub url: String,
+    pub status: StatusCode,
+    pub retry_after: Option<Duration>,
+}
+
+impl StatusError {
+    pub fn new(url: &str, status: StatusCode, headers: &HeaderMap) -> Self {
+        Self {
+            url: url.to_string(),
+            status,
+            retry_after: headers
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+        }
+    }
+
+    /// Server errors, `408 Request Timeout` and `429 Too Many Requests` are temporary
+    pub fn is_retryable(&self) -> bool {
+        self.status.is_server_error()
+            || self.status == StatusCode::REQUEST_TIMEOUT
+            || self.status == StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request {} failed with status: {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// A response body that is shorter or longer than the server announced
+#[derive(Debug)]
+pub struct IncompleteBody {
+    pub url: String,
+    pub expected: u64,
+    pub received: u64,
+}
+
+impl std::fmt::Display for IncompleteBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Received {} of {} bytes from {}",
+            self.received, self.expected, self.url
+        )
+    }
+}
+
+impl std::error::Error for IncompleteBody {}
+
+/// Checks whether the error is transient and the download is worth repeating
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    for cause in error.chain() {
+        if let Some(err) = cause.downcast_ref::<StatusError>() {
+            return err.is_retryable();
+        }
+        if cause.is::<IncompleteBody>() {
+            return true;
+        }
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            return err.is_connect()
+                || err.is_timeout()
+                || err.is_body()
+                || err.is_decode()
+                || err.is_request();
+        }
+    }
+    false
+}
+
+/// Checks whether the error comes from the server rather than the local side,
+/// so another mirror of the file may succeed
+pub fn is_mirror_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<StatusError>()
+            || cause.is::<IncompleteBody>()
+            || cause.is::<ChecksumMismatch>()
+            || cause.is::<reqwest::Error>()
+    })
+}
+
+/// Returns the `Retry-After` delay of the error, if the server sent it
+pub fn retry_after(error: &anyhow::Error) -> Option<Duration> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<StatusError>())
+        .and_then(|err| err.retry_after)
+}
+
+/// Parses `Retry-After` given either in seconds or as an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_is_exponential_and_capped() {
+        let policy = RetryPolicy::new(10)
+            .with_base_delay(Duration::from_secs(2))
+            .with_max_delay(Duration::from_secs(10));
+
+        for (attempt, max) in [(1, 2), (2, 4), (3, 8), (4, 10), (10, 10)] {
+            let delay = policy.delay(attempt, None);
+            let max = Duration::from_secs(max);
+            assert!(
+                delay >= max / 2 && delay <= max,
+                "attempt {}: {:?}",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn test_retry_after_takes_precedence() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(20))),
+            Duration::from_secs(20)
+        );
+    }
+
+    #[test]
+    fn test_retry_after_is_capped() {
+        let policy = RetryPolicy::default().with_max_delay(Duration::from_secs(10));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("5"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_status_is_retryable() {
+        let error = |status| anyhow::Error::new(StatusError::new("url", status, &HeaderMap::new()));
+
+        assert!(is_retryable(&error(StatusCode::INTERNAL_SERVER_ERROR)));
+        assert!(is_retryable(&error(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(is_retryable(&error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(is_retryable(&error(StatusCode::REQUEST_TIMEOUT)));
+        assert!(!is_retryable(&error(StatusCode::NOT_FOUND)));
+        assert!(!is_retryable(&error(StatusCode::FORBIDDEN)));
+        assert!(!is_retryable(&anyhow::anyhow!("File exists")));
+
+        let incomplete = IncompleteBody {
+            url: "url".to_string(),
+            expected: 100,
+            received: 50,
+        };
+        assert!(is_retryable(
+            &anyhow::Error::new(incomplete).context("Download failed")
+        ));
+    }
+}
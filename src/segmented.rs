@@ -0,0 +1,285 @@
+use std::{
+    io::SeekFrom,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::{
+    Client, Response, StatusCode,
+    header::{self, HeaderMap},
+};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+    DownloadTask, Downloader, TaskSettings, auth,
+    resume::ResumeState,
+    retry::{IncompleteBody, StatusError},
+};
+
+/// A part of the file downloaded over a separate connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub start: u64,
+    pub len: u64,
+}
+
+/// Splits the file of `total` bytes into at most `segments` parts of at least `min_segment_size` bytes.
+///
+/// Returns `None` if splitting makes no sense.
+pub fn plan_segments(total: u64, segments: usize, min_segment_size: u64) -> Option<Vec<Segment>> {
+    let count = (segments as u64).min(total / min_segment_size.max(1));
+    if count < 2 {
+        return None;
+    }
+
+    let len = total / count;
+    Some(
+        (0..count)
+            .map(|i| Segment {
+                start: i * len,
+                len: if i == count - 1 { total - i * len } else { len },
+            })
+            .collect(),
+    )
+}
+
+/// The server sent the whole file instead of a segment, as it changed since the first response
+/// and the `If-Range` validator no longer matches
+#[derive(Debug)]
+pub struct ValidatorMismatch {
+    pub url: String,
+}
+
+impl std::fmt::Display for ValidatorMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The file changed during the segmented download: {}", self.url)
+    }
+}
+
+impl std::error::Error for ValidatorMismatch {}
+
+/// Checks whether the segmented download failed because the file changed
+pub fn is_validator_mismatch(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<ValidatorMismatch>())
+}
+
+/// Returns the `If-Range` validator of the response, which ties the segments to the same version of the file
+pub fn validator(response: &Response) -> Option<String> {
+    ResumeState::from_response(response.url().as_str(), response)
+        .and_then(|state| state.if_range().map(str::to_string))
+}
+
+/// Checks whether the server is able to serve parts of the file
+pub fn accepts_ranges(response: &Response) -> bool {
+    response.status() == StatusCode::OK
+        && response
+            .headers()
+            .get(header::ACCEPT_RANGES)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"))
+            })
+}
+
+impl Downloader {
+    /// Downloads the segments concurrently into a preallocated file.
+    ///
+    /// The first segment is read from the already received `response`, the others are requested
+    /// from its final URL with `Range` headers, validated by `If-Range`. On failure the file is truncated to the downloaded contiguous prefix,
+    /// so the next attempt can continue it. Fails with [`ValidatorMismatch`] if the file changed in the meantime.
+    pub(crate) async fn download_segments(
+        client: &Client,
+        task: &DownloadTask,
+        settings: &TaskSettings,
+        headers: &HeaderMap,
+        response: Response,
+        segments: Vec<Segment>,
+    ) -> Result<()> {
+        let total = segments.iter().map(|segment| segment.len).sum();
+        let validator = validator(&response);
+        let url = response.url().clone();
+
+        // Like on redirects, the credentials aren't sent to another host
+        let mut headers = headers.clone();
+        let original = reqwest::Url::parse(&task.url).ok();
+        if url.host_str() != original.as_ref().and_then(|original| original.host_str()) {
+            headers.remove(header::AUTHORIZATION);
+            headers.remove(header::COOKIE);
+        }
+
+        let part = task.part_path();
+        let file = tokio::fs::File::create(&part)
+            .await
+            .with_context(|| format!("Failed to create file: {}", part.display()))?;
+        file.set_len(total)
+            .await
+            .with_context(|| format!("Failed to allocate file: {}", part.display()))?;
+        task.reporter.lock().await.on_file_create(&task.output);
+        task.reporter
+            .lock()
+            .await
+            .on_start_download(&auth::redact_url(response.url().as_str()), &task.output);
+
+        let written: Vec<AtomicU64> = segments.iter().map(|_| AtomicU64::new(0)).collect();
+        let failed = AtomicBool::new(false);
+        let mut response = Some(response);
+
+        let downloads = segments.iter().zip(&written).map(|(&segment, written)| {
+            let response = response.take();
+            let validator = validator.as_deref();
+            let (url, headers) = (&url, &headers);
+            let failed = &failed;
+
+            async move {
+                let result = async {
+                    let response = match response {
+                        Some(response) => response,
+                        None => {
+                            Self::request_segment(client, url, settings, headers, segment, validator).await?
+                        }
+                    };
+                    Self::download_segment(task, settings, response, segment, written, failed).await
+                }
+                .await;
+
+                if result.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                result
+            }
+        });
+
+        let results = futures::future::join_all(downloads).await;
+
+        if let Some(err) = results.into_iter().find_map(Result::err) {
+            // Keep only the contiguous prefix of the file, which can be resumed
+            let mut prefix = 0;
+            for (segment, written) in segments.iter().zip(&written) {
+                let written = written.load(Ordering::Relaxed);
+                prefix += written;
+                if written < segment.len {
+                    break;
+                }
+            }
+
+            file.set_len(prefix).await.ok();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Requests the bytes of the segment, checking that the server returned exactly them
+    async fn request_segment(
+        client: &Client,
+        url: &reqwest::Url,
+        settings: &TaskSettings,
+        headers: &HeaderMap,
+        segment: Segment,
+        validator: Option<&str>,
+    ) -> Result<Response> {
+        let mut request = client.get(url.clone()).headers(headers.clone()).header(
+            header::RANGE,
+            format!("bytes={}-{}", segment.start, segment.start + segment.len - 1),
+        );
+        if let Some(validator) = validator {
+            request = request.header(header::IF_RANGE, validator);
+        }
+
+        let response = settings
+            .until_cancelled(request.send())
+            .await?
+            .map_err(reqwest::Error::without_url)
+            .with_context(|| format!("Failed to GET: '{}'", auth::redact_url(url.as_str())))?;
+
+        if response.status() == StatusCode::OK && validator.is_some() {
+            let url = auth::redact_url(url.as_str()).into_owned();
+            return Err(ValidatorMismatch { url }.into());
+        }
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            let url = auth::redact_url(url.as_str());
+            return Err(StatusError::new(&url, response.status(), response.headers()).into());
+        }
+
+        Self::check_content_range(&response, segment.start)?;
+        Ok(response)
+    }
+
+    /// Writes the first `segment.len` bytes of the response at the segment offset
+    async fn download_segment(
+        task: &DownloadTask,
+        settings: &TaskSettings,
+        response: Response,
+        segment: Segment,
+        written: &AtomicU64,
+        failed: &AtomicBool,
+    ) -> Result<()> {
+        let mut file = open_at(&task.part_path(), segment.start).await?;
+        let mut stream = response.bytes_stream();
+        let mut remaining = segment.len;
+
+        let result = async {
+            while remaining > 0 && !failed.load(Ordering::Relaxed) {
+                let Some(chunk) = settings.until_cancelled(stream.next()).await? else {
+                    // The connection was closed before the end of the segment
+                    return Err(IncompleteBody {
+                        url: task.redacted_url().into_owned(),
+                        expected: segment.len,
+                        received: segment.len - remaining,
+                    }
+                    .into());
+                };
+                let chunk = chunk
+                    .map_err(reqwest::Error::without_url)
+                    .with_context(|| "Failed to read response chunk")?;
+                let chunk = &chunk[..chunk.len().min(remaining as usize)];
+
+                file.write_all(chunk).await?;
+                remaining -= chunk.len() as u64;
+                written.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                task.reporter.lock().await.on_progress(chunk.len() as u64);
+                Self::throttle(task, settings, chunk.len()).await?;
+            }
+            Ok(())
+        }
+        .await;
+
+        // Flushing even on failure, the written bytes may be kept for resuming
+        file.flush().await?;
+        result
+    }
+}
+
+async fn open_at(path: &Path, offset: u64) -> Result<tokio::fs::File> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_segments() {
+        assert_eq!(plan_segments(100, 1, 10), None);
+        assert_eq!(plan_segments(100, 4, 60), None);
+        assert_eq!(
+            plan_segments(100, 3, 10),
+            Some(vec![
+                Segment { start: 0, len: 33 },
+                Segment { start: 33, len: 33 },
+                Segment { start: 66, len: 34 },
+            ])
+        );
+        assert_eq!(plan_segments(100, 8, 40).map(|s| s.len()), Some(2));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+use warp::Filter;
+
+const SUCCESS_MESSAGE: &str = "All files downloaded successfully";
+
+/// A clean directory with an empty config, so the user's config and `.netrc` aren't used
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("downloader-cli-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), "").unwrap();
+    dir
+}
+
+/// Runs the program in `dir`, saving the files to `dir/out`
+async fn run(dir: &Path, args: &[&str]) -> Output {
+    tokio::process::Command::new(env!("CARGO_BIN_EXE_downloader-cli"))
+        .arg("--config")
+        .arg(dir.join("config.toml"))
+        .arg("--dir")
+        .arg(dir.join("out"))
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", dir)
+        .output()
+        .await
+        .unwrap()
+}
+
+fn serve() -> std::net::SocketAddr {
+    let route = warp::path!("file.txt").map(|| "content");
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test]
+async fn test_invalid_list_exits_with_config_code() {
+    let dir = temp_dir("invalid-list");
+    std::fs::write(
+        dir.join("list.txt"),
+        "not a url\nhttps://example.com/a.txt\n  unknown=1\n",
+    )
+    .unwrap();
+
+    let output = run(&dir, &["list.txt"]).await;
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(!stdout.contains(SUCCESS_MESSAGE), "{}", stdout);
+}
+
+#[tokio::test]
+async fn test_partly_invalid_list_exits_with_partial_code() {
+    let addr = serve();
+    let dir = temp_dir("partly-invalid-list");
+    std::fs::write(
+        dir.join("list.txt"),
+        format!("http://{}/file.txt\nnot a url\n", addr),
+    )
+    .unwrap();
+
+    let output = run(&dir, &["list.txt"]).await;
+    let downloaded = std::fs::read_to_string(dir.join("out/file.txt")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(10), "{:?}", output);
+    assert!(!stdout.contains(SUCCESS_MESSAGE), "{}", stdout);
+    assert_eq!(downloaded, "content");
+}
+
+#[tokio::test]
+async fn test_missing_checksum_fails_the_task() {
+    let addr = serve();
+    let dir = temp_dir("missing-checksum");
+    std::fs::write(
+        dir.join("SHA256SUMS"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  other.txt\n",
+    )
+    .unwrap();
+    let url = format!("http://{}/file.txt", addr);
+
+    let output = run(
+        &dir,
+        &[
+            "--require-checksum",
+            "--checksum-file",
+            "SHA256SUMS",
+            "--report",
+            "report.json",
+            &url,
+        ],
+    )
+    .await;
+    let report = std::fs::read_to_string(dir.join("report.json")).unwrap_or_default();
+    let downloaded = dir.join("out/file.txt").exists();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(!downloaded);
+
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["total"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["tasks"][0]["status"], "failed");
+    let error = report["tasks"][0]["error"].as_str().unwrap();
+    assert!(error.starts_with("No checksum"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_checksum_file_is_downloaded_with_credentials() {
+    // Both the file and its checksums need the token
+    let authorized = warp::header::exact("authorization", "Bearer token");
+    let route = authorized
+        .and(warp::path!("SHA256SUMS"))
+        .map(|| "ed7002b439e9ac845f22357d822bac1444730fbdb6016d3ec9432297b9ec9f73  file.txt\n")
+        .or(authorized.and(warp::path!("file.txt")).map(|| "content"));
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    let dir = temp_dir("checksum-file-credentials");
+
+    let output = run(
+        &dir,
+        &[
+            "--bearer",
+            "token",
+            "--require-checksum",
+            "--checksum-file",
+            &format!("http://{}/SHA256SUMS", addr),
+            &format!("http://{}/file.txt", addr),
+        ],
+    )
+    .await;
+    let downloaded = std::fs::read_to_string(dir.join("out/file.txt")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(downloaded, "content");
+}
+
+#[tokio::test]
+async fn test_output_collision_fails_the_task() {
+    let addr = serve();
+    let dir = temp_dir("output-collision");
+    std::fs::write(
+        dir.join("list.txt"),
+        format!(
+            "http://{0}/file.txt\nhttp://{0}/file.txt?v=2\n  out=file.txt\n",
+            addr
+        ),
+    )
+    .unwrap();
+
+    let output = run(&dir, &["list.txt", "--report", "report.json"]).await;
+    let report = std::fs::read_to_string(dir.join("report.json")).unwrap_or_default();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(output.status.code(), Some(10), "{:?}", output);
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["total"], 2);
+    assert_eq!(report["tasks"][1]["status"], "failed");
+    let error = report["tasks"][1]["error"].as_str().unwrap();
+    assert!(error.starts_with("Output path collision"), "{}", error);
+}